@@ -1,18 +1,40 @@
 use engine3d::assets::{Assets, Object2d};
-use engine3d::geom::BBox;
+use engine3d::geom::{BBox, Vec3};
 use engine3d::model::*;
 use engine3d::player::Player;
 use engine3d::render::TwoDID;
+use engine3d::save::{now, SaveDir, WorldMeta, DEFAULT_SAVES_DIR};
+use engine3d::text::{text_image, TextLine};
 use engine3d::voxel::VOXEL_HALFWIDTH;
-use engine3d::{Engine, Game};
+use engine3d::world::World;
+use engine3d::world_gen::{make_world, random_seed, WorldGenParams};
+use engine3d::{Engine, Game, DT};
+use std::time::{Duration, Instant};
 pub type Pos3 = cgmath::Point3<f32>;
 pub type Pos2 = cgmath::Point2<f32>;
 pub type Mat4 = cgmath::Matrix4<f32>;
 pub use winit::event::VirtualKeyCode as KeyCode;
 
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+// The world selection menu is drawn over the title screen as the last 2d object and texture
+const MENU: usize = 4;
+const MENU_ROWS: usize = 8; // Worlds shown at once. The list scrolls to keep the selected one in view
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+const GRAY: [u8; 4] = [170, 170, 170, 255];
+const YELLOW: [u8; 4] = [255, 220, 60, 255];
+const RED: [u8; 4] = [255, 90, 90, 255];
+
 pub struct Game1 {
     twods: Vec<TwoDID>,
     player: Player,
+    hotbar_slot: u8, // Last slot picked while playing, so it can still be saved from the end screen
+    saves: SaveDir,
+    worlds: Vec<WorldMeta>, // Saved worlds, as shown in the title screen menu
+    selected: usize,
+    status: Option<String>, // Shown under the menu, e.g. when a world couldn't be loaded
+    current: Option<WorldMeta>, // The world being played
+    session_start: Instant, // When play time was last added to the current world
+    last_save: Instant,
 }
 #[derive(Debug)]
 pub enum Rule {
@@ -25,15 +47,28 @@ impl Game for Game1 {
     fn start(engine: &mut Engine) -> (Self, Self::StaticData) {
         let mut game = Game1 {
             twods: vec![],
-            player: Player::new(BBox {
-                center: Pos3 {
-                    x: 10.0,
-                    y: 280.0,
-                    z: 10.0,
+            // A bit over half a voxel wide and almost two voxels tall, with eyes near the top
+            player: Player::new(
+                BBox {
+                    center: Pos3 {
+                        x: 10.0,
+                        y: 280.0,
+                        z: 10.0,
+                    },
+                    half_extents: Vec3::new(0.3, 0.9, 0.3) * VOXEL_HALFWIDTH * 2.0,
                 },
-                halfwidth: VOXEL_HALFWIDTH * 0.9,
-            }),
+                1.62 * VOXEL_HALFWIDTH * 2.0,
+            ),
+            hotbar_slot: 1,
+            saves: SaveDir::new(DEFAULT_SAVES_DIR),
+            worlds: vec![],
+            selected: 0,
+            status: None,
+            current: None,
+            session_start: Instant::now(),
+            last_save: Instant::now(),
         };
+        game.refresh_worlds();
         engine.render.sound.add_sound(
             "bgm".to_string(),
             "../content/backgroundmusic.mp3".to_string(),
@@ -161,173 +196,143 @@ impl Game for Game1 {
                     },
                 ],
                 visible: false,
+            }, // world selection menu, placed and drawn by show_menu
+            engine3d::assets::Object2d {
+                bg: MENU,
+                verts: [VertexTwoD {
+                    position: [0.0, 0.0],
+                    tex_coords: [0.0, 0.0],
+                }; 4],
+                visible: true,
             },
         ];
         engine.render.set_2d_bind_groups(&bind_groups);
         game.twods = engine.render.set_2d_buffers(&objects_2d);
+        game.show_menu(engine);
         return (game, Rule::Title);
     }
     fn update(&mut self, rules: &mut Self::StaticData, engine: &mut Engine) {
         match rules {
             Rule::Title => {
-                if engine.events.key_pressed(KeyCode::L) {
-                    engine.render.chunks = engine3d::save::load();
-                    // start gameplay
-                    *rules = Rule::Play(1);
-                    engine.render.sound.play_sound("bgm".to_string());
-                    engine.render.objects_2d[0].2 = false;
+                if !self.worlds.is_empty() {
+                    let count = self.worlds.len();
+                    if engine.events.key_pressed(KeyCode::Up) {
+                        self.selected = (self.selected + count - 1) % count;
+                        self.show_menu(engine);
+                    }
+                    if engine.events.key_pressed(KeyCode::Down) {
+                        self.selected = (self.selected + 1) % count;
+                        self.show_menu(engine);
+                    }
+                    if engine.events.key_pressed(KeyCode::Delete) {
+                        let name = self.worlds[self.selected].name.clone();
+                        self.status = match self.saves.delete(&name) {
+                            Ok(()) => Some(format!("Deleted {}", name)),
+                            Err(e) => {
+                                eprintln!("delete failed: {:?}", e);
+                                Some(format!("Could not delete {}", name))
+                            }
+                        };
+                        self.refresh_worlds();
+                        self.show_menu(engine);
+                    }
+                }
+                if engine.events.key_pressed(KeyCode::L)
+                    || engine.events.key_pressed(KeyCode::Return)
+                {
+                    match self
+                        .worlds
+                        .get(self.selected)
+                        .map(|meta| self.saves.load(&meta.name, &engine.render.blocks))
+                    {
+                        Some(Ok(saved)) => {
+                            let mut slot = 1;
+                            // Worlds saved without a player start at the spawn point
+                            if let Some(state) = saved.player {
+                                self.player.restore(&state, &mut engine.render.camera);
+                                slot = state.hotbar_slot.clamp(1, 5);
+                                show_hotbar_highlight(engine, slot);
+                            }
+                            // Set the world after moving the camera so the right chunks stream in
+                            engine.render.set_world(saved.world);
+                            println!("succesful load of {}", saved.meta.name);
+                            if let Some(backup) = saved.recovered_from {
+                                eprintln!(
+                                    "the latest save was unreadable, loaded {} instead",
+                                    backup.display()
+                                );
+                            }
+                            self.current = Some(saved.meta);
+                            self.start_playing(rules, engine, slot);
+                        }
+                        Some(Err(e)) => {
+                            eprintln!("load failed: {:?}", e);
+                            self.status = Some(format!(
+                                "Could not load {}",
+                                self.worlds[self.selected].name
+                            ));
+                            self.show_menu(engine);
+                        }
+                        None => {
+                            self.status = Some("There are no saved worlds to load".to_string());
+                            self.show_menu(engine);
+                        }
+                    }
                 }
                 if engine.events.key_pressed(KeyCode::Space) {
-                    *rules = Rule::Play(1);
-                    engine.render.sound.play_sound("bgm".to_string());
-                    engine.render.objects_2d[0].2 = false;
-                    // change rule to play
+                    let seed = random_seed();
+                    let name = self.saves.unused_name();
+                    println!("new world {} with seed: {}", name, seed);
+                    let blocks = engine.render.blocks.clone();
+                    engine
+                        .render
+                        .set_world(make_world(seed, WorldGenParams::default(), blocks));
+                    self.current = Some(WorldMeta::new(&name, seed));
+                    self.start_playing(rules, engine, 1);
                 }
             }
-            Rule::Play(i) => {
+            &mut Rule::Play(i) => {
                 self.player.process_events(&engine.events);
-                engine.render.input(&engine.events, *i);
-                self.player
-                    .update(&mut engine.render.camera, &engine.render.chunks);
+                engine.render.input(&engine.events, i, &self.player.hitbox);
+                // Hold the player in place until the ground under them has been generated
+                let player_chunk = World::world_to_chunk(self.player.get_pos()).0;
+                let below_chunk = (player_chunk.0, player_chunk.1 - 1, player_chunk.2);
+                if engine.render.chunk_state(player_chunk).is_loaded()
+                    && engine.render.chunk_state(below_chunk).is_loaded()
+                {
+                    self.player
+                        .update(&mut engine.render.camera, &engine.render.world, DT);
+                }
                 engine.render.update();
                 // Change this with new camera code and stuff
                 // render gameplay relevent stuff
 
                 // render hotbar + hotbar highlight
                 engine.render.objects_2d[1].2 = true;
-                let mut moved_highlight = Object2d {
-                    bg: 2,
-                    verts: [
-                        VertexTwoD {
-                            position: [-0.855, -0.551],
-                            tex_coords: [0.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.586, -0.551],
-                            tex_coords: [0.0, 1.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.855, -0.827],
-                            tex_coords: [1.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.586, -0.827],
-                            tex_coords: [1.0, 1.0],
-                        },
-                    ],
-                    visible: false,
-                };
                 if engine.events.key_pressed(KeyCode::Q) {
+                    self.hotbar_slot = i;
                     *rules = Rule::End;
                     engine.render.objects_2d[1].2 = false;
                     engine.render.objects_2d[2].2 = false;
                 }
 
-                if engine.events.key_pressed(KeyCode::Key1) {
-                    *rules = Rule::Play(1);
-                    engine
-                        .render
-                        .update_2d_buffer(&moved_highlight, engine.render.objects_2d[2]);
-                    engine.render.objects_2d[2].2 = true;
-                } else if engine.events.key_pressed(KeyCode::Key2) {
-                    *rules = Rule::Play(2);
-                    moved_highlight.verts = [
-                        VertexTwoD {
-                            position: [-0.563, -0.551],
-                            tex_coords: [0.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.293, -0.551],
-                            tex_coords: [0.0, 1.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.563, -0.84],
-                            tex_coords: [1.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.293, -0.84],
-                            tex_coords: [1.0, 1.0],
-                        },
-                    ];
-                    engine
-                        .render
-                        .update_2d_buffer(&moved_highlight, engine.render.objects_2d[2]);
-                    engine.render.objects_2d[2].2 = true;
-                } else if engine.events.key_pressed(KeyCode::Key3) {
-                    *rules = Rule::Play(3);
-                    moved_highlight.verts = [
-                        VertexTwoD {
-                            position: [-0.273, -0.551],
-                            tex_coords: [0.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.004, -0.551],
-                            tex_coords: [0.0, 1.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.273, -0.84],
-                            tex_coords: [1.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [-0.004, -0.84],
-                            tex_coords: [1.0, 1.0],
-                        },
-                    ];
-                    engine
-                        .render
-                        .update_2d_buffer(&moved_highlight, engine.render.objects_2d[2]);
-                    engine.render.objects_2d[2].2 = true;
-                } else if engine.events.key_pressed(KeyCode::Key4) {
-                    *rules = Rule::Play(4);
-                    moved_highlight.verts = [
-                        VertexTwoD {
-                            position: [0.016, -0.551],
-                            tex_coords: [0.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [0.286, -0.551],
-                            tex_coords: [0.0, 1.0],
-                        },
-                        VertexTwoD {
-                            position: [0.016, -0.84],
-                            tex_coords: [1.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [0.286, -0.84],
-                            tex_coords: [1.0, 1.0],
-                        },
-                    ];
-                    engine
-                        .render
-                        .update_2d_buffer(&moved_highlight, engine.render.objects_2d[2]);
-                    engine.render.objects_2d[2].2 = true;
-                } else if engine.events.key_pressed(KeyCode::Key5) {
-                    *rules = Rule::Play(5);
-                    moved_highlight.verts = [
-                        VertexTwoD {
-                            position: [0.308, -0.551],
-                            tex_coords: [0.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [0.578, -0.551],
-                            tex_coords: [0.0, 1.0],
-                        },
-                        VertexTwoD {
-                            position: [0.308, -0.84],
-                            tex_coords: [1.0, 0.0],
-                        },
-                        VertexTwoD {
-                            position: [0.578, -0.84],
-                            tex_coords: [1.0, 1.0],
-                        },
-                    ];
-                    engine
-                        .render
-                        .update_2d_buffer(&moved_highlight, engine.render.objects_2d[2]);
-                    engine.render.objects_2d[2].2 = true;
+                let hotbar_keys = [
+                    KeyCode::Key1,
+                    KeyCode::Key2,
+                    KeyCode::Key3,
+                    KeyCode::Key4,
+                    KeyCode::Key5,
+                ];
+                if let Some(slot) =
+                    (1..=5).find(|slot| engine.events.key_pressed(hotbar_keys[*slot as usize - 1]))
+                {
+                    *rules = Rule::Play(slot);
+                    show_hotbar_highlight(engine, slot);
                 }
-                if engine.events.key_pressed(KeyCode::T) {
-                    engine3d::save::save(&engine.render.chunks);
+                if engine.events.key_pressed(KeyCode::T)
+                    || self.last_save.elapsed() >= AUTOSAVE_INTERVAL
+                {
+                    self.save_game(engine, i);
                 }
             }
             Rule::End => {
@@ -336,7 +341,7 @@ impl Game for Game1 {
                     panic!();
                 }
                 if engine.events.key_pressed(KeyCode::S) {
-                    engine3d::save::save(&engine.render.chunks);
+                    self.save_game(engine, self.hotbar_slot);
                 }
             }
         }
@@ -345,6 +350,152 @@ impl Game for Game1 {
     fn render(&mut self, rules: &Self::StaticData, assets: &Assets) {}
 }
 
+impl Game1 {
+    fn refresh_worlds(&mut self) {
+        self.worlds = self.saves.list().unwrap_or_else(|e| {
+            eprintln!("could not list saved worlds: {:?}", e);
+            vec![]
+        });
+        self.selected = self.selected.min(self.worlds.len().saturating_sub(1));
+    }
+
+    /// Draws the list of saved worlds over the title screen
+    fn show_menu(&self, engine: &mut Engine) {
+        let mut lines = vec![TextLine::new("Worlds", WHITE), TextLine::new("", WHITE)];
+        let now = now();
+        let first = (self.selected + 1).saturating_sub(MENU_ROWS);
+        for (i, meta) in self.worlds.iter().enumerate().skip(first).take(MENU_ROWS) {
+            let selected = i == self.selected;
+            lines.push(TextLine::new(
+                format!(
+                    "{} {}  seed {}  played {}  last played {} ago",
+                    if selected { ">" } else { " " },
+                    meta.name,
+                    meta.seed,
+                    format_duration(meta.play_time as u64),
+                    format_duration(now.saturating_sub(meta.last_played)),
+                ),
+                if selected { YELLOW } else { GRAY },
+            ));
+        }
+        if self.worlds.is_empty() {
+            lines.push(TextLine::new("  No saved worlds yet", GRAY));
+        }
+        lines.push(TextLine::new("", WHITE));
+        lines.push(TextLine::new(
+            "Up/Down: pick  Enter: load  Delete: remove  Space: new world",
+            WHITE,
+        ));
+        if let Some(status) = &self.status {
+            lines.push(TextLine::new(status.clone(), RED));
+        }
+        let image = text_image(&lines);
+
+        // As big as fits below the logo, without stretching the text
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let scale = (1.6 / width).min(0.9 / (height * engine.render.camera.aspect));
+        let (half_width, height) = (
+            width * scale / 2.0,
+            height * scale * engine.render.camera.aspect,
+        );
+        let (top, bottom) = (-0.05, -0.05 - height);
+        let corner = |x: f32, y: f32, u: f32, v: f32| VertexTwoD {
+            position: [x, y],
+            tex_coords: [u, v],
+        };
+        let menu = Object2d {
+            bg: MENU,
+            verts: [
+                corner(-half_width, top, 0.0, 0.0),
+                corner(-half_width, bottom, 0.0, 1.0),
+                corner(half_width, top, 1.0, 0.0),
+                corner(half_width, bottom, 1.0, 1.0),
+            ],
+            visible: true,
+        };
+        engine.render.set_2d_image(MENU, &image);
+        engine
+            .render
+            .update_2d_buffer(&menu, engine.render.objects_2d[MENU]);
+    }
+
+    fn start_playing(&mut self, rules: &mut Rule, engine: &mut Engine, slot: u8) {
+        self.session_start = Instant::now();
+        self.last_save = Instant::now();
+        *rules = Rule::Play(slot);
+        engine.render.sound.play_sound("bgm".to_string());
+        engine.render.objects_2d[0].2 = false;
+        engine.render.objects_2d[MENU].2 = false;
+    }
+
+    fn save_game(&mut self, engine: &mut Engine, hotbar_slot: u8) {
+        self.last_save = Instant::now();
+        let meta = match &mut self.current {
+            Some(meta) => meta,
+            None => return,
+        };
+        meta.play_time += self.session_start.elapsed().as_secs_f64();
+        self.session_start = Instant::now();
+        let player = self.player.state(&engine.render.camera, hotbar_slot);
+        let changed = engine.render.world.dirty_chunks().count();
+        match self.saves.save(meta, &mut engine.render.world, &player) {
+            Ok(()) => println!(
+                "sucessful save of {} ({} changed chunks)",
+                meta.name, changed
+            ),
+            Err(e) => eprintln!("save failed: {:?}", e),
+        }
+    }
+}
+
+/// Rough human readable length of time, e.g. "3h 12m"
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h {}m", s / 3600, s / 60 % 60),
+        s => format!("{}d {}h", s / 86400, s / 3600 % 24),
+    }
+}
+
+/// Moves the hotbar highlight over a slot (1 to 5) and shows it
+fn show_hotbar_highlight(engine: &mut Engine, slot: u8) {
+    // left edge, right edge and bottom edge of each slot
+    let (left, right, bottom) = match slot {
+        1 => (-0.855, -0.586, -0.827),
+        2 => (-0.563, -0.293, -0.84),
+        3 => (-0.273, -0.004, -0.84),
+        4 => (0.016, 0.286, -0.84),
+        _ => (0.308, 0.578, -0.84),
+    };
+    let highlight = Object2d {
+        bg: 2,
+        verts: [
+            VertexTwoD {
+                position: [left, -0.551],
+                tex_coords: [0.0, 0.0],
+            },
+            VertexTwoD {
+                position: [right, -0.551],
+                tex_coords: [0.0, 1.0],
+            },
+            VertexTwoD {
+                position: [left, bottom],
+                tex_coords: [1.0, 0.0],
+            },
+            VertexTwoD {
+                position: [right, bottom],
+                tex_coords: [1.0, 1.0],
+            },
+        ],
+        visible: false,
+    };
+    engine
+        .render
+        .update_2d_buffer(&highlight, engine.render.objects_2d[2]);
+    engine.render.objects_2d[2].2 = true;
+}
+
 fn main() {
     let title = "mimecraft";
     let asset_root = std::path::Path::new(env!("OUT_DIR")).join("content");
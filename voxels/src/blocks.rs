@@ -0,0 +1,198 @@
+use crate::light::MAX_LIGHT;
+use crate::mesher::Face;
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Id of the empty block. Every registry has it, and it's what unloaded space counts as
+pub const AIR: u8 = 0;
+
+/// The file that lists every block, in the content folder
+pub const BLOCKS_FILE: &str = "blocks.json";
+
+/// Which texture each face of a block uses, with all filling in any that aren't given.
+/// Block textures are laid out like cube.obj's uvs with one face per cell, and a face uses
+/// its own cell of whichever texture it gets. Every block texture has to be the same size
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaceTextures {
+    pub all: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub side: Option<String>,
+}
+
+impl FaceTextures {
+    pub fn get(&self, face: Face) -> Option<&str> {
+        let texture = match face {
+            Face::PosY => &self.top,
+            Face::NegY => &self.bottom,
+            _ => &self.side,
+        };
+        texture.as_ref().or(self.all.as_ref()).map(String::as_str)
+    }
+}
+
+/// How a block's faces get drawn. Cutout textures are either see-through or not, with nothing in between,
+/// and translucent ones are blended with whatever is behind them
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    #[default]
+    Opaque,
+    Cutout,
+    Translucent,
+}
+
+/// Everything about one type of block. Ids are what gets stored in chunks and saves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockType {
+    pub id: u8,
+    pub name: String,
+    #[serde(default)]
+    pub textures: FaceTextures,
+    #[serde(default)]
+    pub hardness: f32, // How long the block takes to break
+    #[serde(default)]
+    pub material: Option<String>, // What it's made of, which decides the tools that break it faster
+    #[serde(default = "yes")]
+    pub breakable: bool,
+    #[serde(default = "yes")]
+    pub solid: bool, // The player can't walk through it
+    #[serde(default)]
+    pub transparent: bool, // Light goes through it, and it doesn't hide the faces behind it
+    #[serde(default)]
+    pub render: RenderMode,
+    #[serde(default)]
+    pub break_sound: Option<String>,
+    #[serde(default)]
+    pub place_sound: Option<String>,
+    #[serde(default)]
+    pub emission: u8, // Block light it gives off, up to MAX_LIGHT
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// Every type of block, loaded from a JSON list of BlockTypes
+#[derive(Clone, Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockType>,                 // Indexed by id
+    textures: Vec<String>,                  // Every texture a block uses, once each
+    face_textures: Vec<[Option<usize>; 6]>, // Index into textures for each block's faces, in Face::ALL order
+    ids: HashMap<String, u8>,
+}
+
+impl BlockRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("could not load {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Self::new(serde_json::from_str(json)?)
+    }
+
+    /// Checks over the blocks and builds the registry. Ids have to count up from AIR without any gaps
+    pub fn new(mut blocks: Vec<BlockType>) -> Result<Self> {
+        ensure!(!blocks.is_empty(), "there are no blocks");
+        blocks.sort_by_key(|block| block.id);
+        let mut ids = HashMap::new();
+        let mut textures: Vec<String> = vec![];
+        let mut face_textures = vec![];
+        for (i, block) in blocks.iter().enumerate() {
+            ensure!(
+                block.id as usize == i,
+                "expected a block with id {} before {:?}",
+                i,
+                block.name
+            );
+            ensure!(
+                ids.insert(block.name.clone(), block.id).is_none(),
+                "there is more than one block named {:?}",
+                block.name
+            );
+            ensure!(
+                block.emission <= MAX_LIGHT,
+                "{:?} gives off more than the max light of {}",
+                block.name,
+                MAX_LIGHT
+            );
+            ensure!(
+                block.transparent || block.render == RenderMode::Opaque,
+                "{:?} is drawn {:?} so it has to be transparent",
+                block.name,
+                block.render
+            );
+            let mut faces = [None; 6];
+            if block.id == AIR {
+                ensure!(
+                    block.transparent && !block.solid && block.textures == FaceTextures::default(),
+                    "block {} has to be empty, see-through and untextured",
+                    AIR
+                );
+            } else {
+                for (texture_index, face) in faces.iter_mut().zip(Face::ALL.iter()) {
+                    let texture = block.textures.get(*face).with_context(|| {
+                        format!("{:?} has no texture for its {:?} face", block.name, face)
+                    })?;
+                    *texture_index =
+                        Some(match textures.iter().position(|known| known == texture) {
+                            Some(index) => index,
+                            None => {
+                                textures.push(texture.to_string());
+                                textures.len() - 1
+                            }
+                        });
+                }
+            }
+            face_textures.push(faces);
+        }
+        Ok(Self {
+            blocks,
+            textures,
+            face_textures,
+            ids,
+        })
+    }
+
+    /// The block with this id. Ids that aren't registered act like air
+    pub fn get(&self, id: u8) -> &BlockType {
+        self.blocks
+            .get(id as usize)
+            .unwrap_or(&self.blocks[AIR as usize])
+    }
+
+    /// Looks up a block's id by its name
+    pub fn id(&self, name: &str) -> Option<u8> {
+        self.ids.get(name).copied()
+    }
+
+    /// Every texture file that blocks use, in the order of the layers of the block texture array
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+
+    /// Index into textures for one face of a block. None for air (and unknown ids)
+    pub fn face_texture(&self, id: u8, face: Face) -> Option<usize> {
+        self.face_textures
+            .get(id as usize)
+            .and_then(|faces| faces[face.index()])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockType> {
+        self.blocks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
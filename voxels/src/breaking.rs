@@ -0,0 +1,81 @@
+use crate::blocks::BlockType;
+use crate::light::VoxelPos;
+use serde::{Deserialize, Serialize};
+
+/// Seconds it takes to break a block with a hardness of 1 by hand
+pub const SECONDS_PER_HARDNESS: f32 = 1.0;
+
+/// How many different crack overlays a block goes through while it's being broken
+pub const CRACK_STAGES: u8 = 10;
+
+/// Whatever the player is holding while they break blocks. It only speeds up breaking blocks made of
+/// the materials it's meant for, anything else breaks as slowly as it would by hand
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    pub speed: f32, // How many times faster than by hand it breaks the blocks it's meant for
+    #[serde(default)]
+    pub materials: Vec<String>, // The block materials it's meant for
+}
+
+impl Tool {
+    /// Breaking blocks with nothing in hand
+    pub fn hand() -> Self {
+        Self {
+            name: "hand".to_string(),
+            speed: 1.0,
+            materials: vec![],
+        }
+    }
+
+    /// How many times faster than by hand it breaks the block
+    pub fn speed_on(&self, block: &BlockType) -> f32 {
+        match &block.material {
+            Some(material) if self.materials.contains(material) => self.speed,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Progress on breaking the block that the player is holding the mouse down on
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Breaking {
+    pub target: Option<(VoxelPos, u8)>, // The voxel being broken and the block that was in it
+    pub progress: f32,                  // From 0.0 up to 1.0, when the block breaks
+}
+
+impl Breaking {
+    /// Keeps breaking the target block with a tool for another dt seconds. Breaking starts over whenever
+    /// the target changes, and stops when there isn't one (the mouse was let go or nothing is in reach).
+    /// Returns true when the block breaks
+    pub fn update(&mut self, target: Option<(VoxelPos, &BlockType)>, tool: &Tool, dt: f32) -> bool {
+        let (voxel, block) = match target {
+            Some((voxel, block)) if block.breakable => (voxel, block),
+            _ => {
+                *self = Self::default();
+                return false;
+            }
+        };
+        if self.target != Some((voxel, block.id)) {
+            self.target = Some((voxel, block.id));
+            self.progress = 0.0;
+        }
+        let speed = tool.speed_on(block);
+        if block.hardness <= 0.0 {
+            self.progress = 1.0;
+        } else if speed > 0.0 {
+            self.progress += dt * speed / (block.hardness * SECONDS_PER_HARDNESS);
+        }
+        if self.progress >= 1.0 {
+            *self = Self::default();
+            return true;
+        }
+        false
+    }
+
+    /// Which crack overlay to show, from 0 up to CRACK_STAGES - 1. None if nothing is being broken
+    pub fn stage(&self) -> Option<u8> {
+        self.target
+            .map(|_| ((self.progress * CRACK_STAGES as f32) as u8).min(CRACK_STAGES - 1))
+    }
+}
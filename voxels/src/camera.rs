@@ -14,4 +14,39 @@ impl Camera {
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
         proj * view
     }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.build_view_projection_matrix())
+    }
+}
+
+/// The six planes around everything the camera can see, in the order left, right, bottom, top, near, far.
+/// Each plane is (a, b, c, d) and faces inwards, so a point is on its inside when ax + by + cz + d >= 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [cgmath::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Pulls the planes out of a view projection matrix (see Gribb and Hartmann's
+    /// "Fast Extraction of Viewing Frustum Planes from the World-View-Projection Matrix")
+    pub fn from_matrix(m: cgmath::Matrix4<f32>) -> Self {
+        let row = |i: usize| cgmath::Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    /// Could any of the box between min and max be on screen? It's conservative, so boxes just
+    /// outside of the frustum's corners can still count as inside
+    pub fn intersects_aabb(&self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the plane's normal
+            let x = if plane.x >= 0.0 { max.x } else { min.x };
+            let y = if plane.y >= 0.0 { max.y } else { min.y };
+            let z = if plane.z >= 0.0 { max.z } else { min.z };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
 }
@@ -1,39 +1,242 @@
-use crate::coordinates::*;
+use crate::blocks::AIR;
+use crate::camera::Camera;
 use crate::geom::*;
-use crate::voxel::*;
-
-pub fn collide_x(hitbox: BBox, chunks: &Vec<Chunk>, x: f32) -> bool {
-    let hitbox_center = hitbox.center;
-    let halfwidth = hitbox.halfwidth;
-    let border_check = cgmath::point3(
-        hitbox_center.x + halfwidth * x.signum() + x,
-        hitbox_center.y,
-        hitbox_center.z,
-    );
-    let (i, (x, y, z)) = world_to_chunk(border_check);
-    chunks[i].data[x][y][z] != 0
+use crate::light::VoxelPos;
+use crate::voxel::VOXEL_HALFWIDTH;
+use crate::world::World;
+
+/// The furthest a ray goes, in world units. Longer max distances are cut down to this,
+/// since nothing that far away is loaded anyway
+pub const MAX_RAY_DIST: f32 = 1024.0 * VOXEL_HALFWIDTH * 2.0;
+
+/// Where a ray first runs into a block
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub voxel: VoxelPos,
+    pub face_normal: [i32; 3], // Points out of the face the ray went in through. All zero if the ray started inside of the voxel
+    pub distance: f32,         // From the ray's origin to where it hit, in world units
+}
+
+impl RayHit {
+    /// The voxel in front of the face that was hit, where a block placed against it would go
+    pub fn adjacent(&self) -> VoxelPos {
+        let [x, y, z] = self.voxel;
+        let [nx, ny, nz] = self.face_normal;
+        [x + nx, y + ny, z + nz]
+    }
+}
+
+impl World {
+    /// Is the point inside of a solid block? Unloaded chunks count as empty space
+    pub fn is_solid(&self, point: Pos3) -> bool {
+        self.voxel(point)
+            .map(|voxel| self.blocks().get(voxel).solid)
+            .unwrap_or(false)
+    }
+
+    /// Finds the first block (anything but air) along a ray, stepping through every voxel the ray passes
+    /// through in order (see Amanatides and Woo's "A Fast Voxel Traversal Algorithm for Ray Tracing").
+    /// Unloaded chunks count as empty space. Rays that would never end (a zero direction, or anything
+    /// infinite or NaN) hit nothing, and none go further than MAX_RAY_DIST
+    pub fn raycast(&self, origin: Pos3, dir: Vec3, max_dist: f32) -> Option<RayHit> {
+        let finite = |v: [f32; 3]| v.iter().all(|x| x.is_finite());
+        if !max_dist.is_finite() || !finite(origin.into()) || !finite(dir.into()) {
+            return None;
+        }
+        let max_dist = max_dist.min(MAX_RAY_DIST);
+        // Scaled down first so that squaring very long or very short directions can't overflow or underflow
+        let largest = dir.x.abs().max(dir.y.abs()).max(dir.z.abs());
+        if largest == 0.0 {
+            return None;
+        }
+        let dir = dir / largest;
+        let length = (dir.x * dir.x + dir.y * dir.y + dir.z * dir.z).sqrt();
+        let dir = [dir.x / length, dir.y / length, dir.z / length];
+        let origin = [origin.x, origin.y, origin.z];
+        let vox_scale = VOXEL_HALFWIDTH * 2.0;
+
+        let mut voxel = [0; 3];
+        let mut step = [0; 3];
+        // How far along the ray the next boundary on each axis is, and how far apart the boundaries are
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let start = origin[axis] / vox_scale;
+            voxel[axis] = start.floor() as i32;
+            if dir[axis] > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (voxel[axis] as f32 + 1.0 - start) * vox_scale / dir[axis];
+            } else if dir[axis] < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (start - voxel[axis] as f32) * vox_scale / -dir[axis];
+            }
+            if dir[axis] != 0.0 {
+                t_delta[axis] = vox_scale / dir[axis].abs();
+            }
+        }
+
+        // Every step crosses a boundary on one of the axes, and a ray can only cross so many of them
+        // before it's gone the max distance. Counting them keeps rays that start out so far away that
+        // the steps get lost to rounding from going on forever
+        let max_steps = 3 * (max_dist / vox_scale).ceil() as usize + 3;
+        let mut face_normal = [0; 3];
+        let mut distance = 0.0;
+        for _ in 0..=max_steps {
+            if matches!(self.voxel_at(voxel), Some(v) if v != AIR) {
+                return Some(RayHit {
+                    voxel,
+                    face_normal,
+                    distance,
+                });
+            }
+            // Step into the next voxel across whichever boundary comes first
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            distance = t_max[axis];
+            if distance > max_dist {
+                return None;
+            }
+            voxel[axis] = voxel[axis].wrapping_add(step[axis]);
+            t_max[axis] += t_delta[axis];
+            face_normal = [0; 3];
+            face_normal[axis] = -step[axis];
+        }
+        None
+    }
+
+    /// The block in the middle of the camera's view, if it's within reach of the eye.
+    /// This is the block that gets outlined, broken and built on
+    pub fn looked_at(&self, camera: &Camera, reach: f32) -> Option<RayHit> {
+        self.raycast(camera.eye, camera.target - camera.eye, reach)
+    }
+
+    /// Is the voxel at a position counted in voxels from the world origin solid?
+    pub fn is_solid_voxel(&self, pos: VoxelPos) -> bool {
+        self.voxel_at(pos)
+            .map(|voxel| self.blocks().get(voxel).solid)
+            .unwrap_or(false)
+    }
+
+    /// Does the box reach into the voxel at a position counted in voxels from the world origin? Just
+    /// touching one of its faces doesn't count
+    pub fn overlaps_voxel(hitbox: &BBox, pos: VoxelPos) -> bool {
+        (0..3).all(|axis| {
+            let min = hitbox.center[axis] - hitbox.half_extents[axis];
+            let max = hitbox.center[axis] + hitbox.half_extents[axis];
+            min + SKIN < (pos[axis] + 1) as f32 * VOXEL_SIZE
+                && max - SKIN > pos[axis] as f32 * VOXEL_SIZE
+        })
+    }
+
+    /// Moves a box by movement, stopping it against any solid voxels in the way. Each axis is moved
+    /// in turn (x, then y, then z) starting from where the last one left the box, and checks every
+    /// voxel the box sweeps through, so it can't slip through edges or corners. Voxels the box
+    /// already overlaps don't stop it, so it can't get stuck inside of a block
+    pub fn sweep(&self, hitbox: BBox, movement: Vec3) -> Sweep {
+        let mut center = [hitbox.center.x, hitbox.center.y, hitbox.center.z];
+        let half = [
+            hitbox.half_extents.x,
+            hitbox.half_extents.y,
+            hitbox.half_extents.z,
+        ];
+        let movement = [movement.x, movement.y, movement.z];
+        let mut normal = [0; 3];
+        for axis in 0..3 {
+            let delta = movement[axis];
+            if delta == 0.0 {
+                continue;
+            }
+            let min = |c: &[f32; 3], i: usize| c[i] - half[i];
+            let max = |c: &[f32; 3], i: usize| c[i] + half[i];
+            // Voxels the box covers on the other two axes. Just touching one doesn't count
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let covered = |i: usize| {
+                let lo = ((min(&center, i) + SKIN) / VOXEL_SIZE).floor() as i32;
+                let hi = ((max(&center, i) - SKIN) / VOXEL_SIZE).ceil() as i32 - 1;
+                lo..=hi
+            };
+            let slab_is_solid = |layer: i32| {
+                covered(u).any(|a| {
+                    covered(v).any(|b| {
+                        let mut pos = [0; 3];
+                        pos[axis] = layer;
+                        pos[u] = a;
+                        pos[v] = b;
+                        self.is_solid_voxel(pos)
+                    })
+                })
+            };
+            // Step through the layers of voxels in front of the box along the axis, nearest first
+            let mut allowed = delta;
+            if delta > 0.0 {
+                let front = max(&center, axis);
+                let first = ((front - SKIN) / VOXEL_SIZE).ceil() as i32;
+                let last = ((front + delta) / VOXEL_SIZE).floor() as i32;
+                if let Some(layer) = (first..=last).find(|layer| slab_is_solid(*layer)) {
+                    allowed = (layer as f32 * VOXEL_SIZE - front).max(0.0).min(delta);
+                    normal[axis] = -1;
+                }
+            } else {
+                let front = min(&center, axis);
+                let first = ((front + SKIN) / VOXEL_SIZE).floor() as i32 - 1;
+                let last = ((front + delta) / VOXEL_SIZE).floor() as i32;
+                if let Some(layer) = (last..=first).rev().find(|layer| slab_is_solid(*layer)) {
+                    allowed = ((layer + 1) as f32 * VOXEL_SIZE - front)
+                        .min(0.0)
+                        .max(delta);
+                    normal[axis] = 1;
+                }
+            }
+            center[axis] += allowed;
+        }
+        let center = Pos3::from(center);
+        Sweep {
+            hitbox: BBox { center, ..hitbox },
+            moved: center - hitbox.center,
+            normal,
+        }
+    }
+
+    /// Is the box standing on something solid, so that it would land straight away if it fell?
+    pub fn is_on_ground(&self, hitbox: BBox) -> bool {
+        self.sweep(hitbox, Vec3::new(0.0, -GROUND_PROBE, 0.0))
+            .on_ground()
+    }
 }
 
-pub fn collide_y(hitbox: BBox, chunks: &Vec<Chunk>, y: f32) -> bool {
-    let hitbox_center = hitbox.center;
-    let halfwidth = hitbox.halfwidth;
-    let border_check = cgmath::point3(
-        hitbox_center.x,
-        hitbox_center.y + halfwidth * y.signum() + y,
-        hitbox_center.z,
-    );
-    let (i, (x, y, z)) = world_to_chunk(border_check);
-    chunks[i].data[x][y][z] != 0
+const VOXEL_SIZE: f32 = VOXEL_HALFWIDTH * 2.0;
+
+// How far under a box to look for ground. Anything closer than this counts as standing on it
+const GROUND_PROBE: f32 = 0.05;
+
+// Leeway for rounding errors, so a box resting against a voxel isn't counted as overlapping it
+const SKIN: f32 = 1e-3;
+
+/// Where a box ended up after World::sweep moved it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sweep {
+    pub hitbox: BBox,
+    pub moved: Vec3, // How far the box actually moved, which is less than asked for on any axis it was stopped on
+    pub normal: [i32; 3], // For each axis, the way the box was pushed back by what it ran into (so 1 on y is landing on the ground). 0 if nothing got in the way
 }
 
-pub fn collide_z(hitbox: BBox, chunks: &Vec<Chunk>, z: f32) -> bool {
-    let hitbox_center = hitbox.center;
-    let halfwidth = hitbox.halfwidth;
-    let border_check = cgmath::point3(
-        hitbox_center.x,
-        hitbox_center.y,
-        hitbox_center.z + halfwidth * z.signum() + z,
-    );
-    let (i, (x, y, z)) = world_to_chunk(border_check);
-    chunks[i].data[x][y][z] != 0
+impl Sweep {
+    /// Did the box come down on top of something?
+    pub fn on_ground(&self) -> bool {
+        self.normal[1] == 1
+    }
+
+    /// Did the box bump into something above it?
+    pub fn hit_ceiling(&self) -> bool {
+        self.normal[1] == -1
+    }
 }
@@ -0,0 +1,18 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in float v_stage;
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    // Split the face into pixels the same size as the block textures' and pick some of them to crack,
+    // starting in the middle and spreading out to the edges as the stage goes up
+    vec2 pixel = floor(v_tex_coords * 16.0);
+    float noise = fract(sin(dot(pixel, vec2(12.9898, 78.233))) * 43758.5453);
+    float from_middle = length(pixel + 0.5 - 8.0) / 8.0;
+    if (from_middle > v_stage * 1.5 || noise > 0.2 + 0.3 * v_stage) {
+        discard;
+    }
+    f_color = vec4(0.0, 0.0, 0.0, 0.6);
+}
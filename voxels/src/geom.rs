@@ -23,52 +23,57 @@ pub struct Plane {
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct BBox {
     pub center: Pos3,
-    pub halfwidth: f32, // Width, height, depth
+    pub half_extents: Vec3, // Half of the width, height and depth
 }
 
 pub fn touching_box_box(b1: &BBox, b2: &BBox) -> bool {
-    if (b1.halfwidth + b2.halfwidth) < (b1.center.x - b2.center.x).abs() {
+    if (b1.half_extents.x + b2.half_extents.x) < (b1.center.x - b2.center.x).abs() {
         return false;
     }
-    if (b1.halfwidth + b2.halfwidth) < (b1.center.y - b2.center.y).abs() {
+    if (b1.half_extents.y + b2.half_extents.y) < (b1.center.y - b2.center.y).abs() {
         return false;
     }
-    if (b1.halfwidth + b2.halfwidth).abs() < (b1.center.z - b2.center.z).abs() {
+    if (b1.half_extents.z + b2.half_extents.z).abs() < (b1.center.z - b2.center.z).abs() {
         return false;
     }
     return true;
 }
 
+/// The point in (or on) b nearest to p. That's p itself if it's inside of b
+pub fn closest_point_box(b: &BBox, p: Pos3) -> Pos3 {
+    let min = b.center - b.half_extents;
+    let max = b.center + b.half_extents;
+    Pos3::new(
+        p.x.max(min.x).min(max.x),
+        p.y.max(min.y).min(max.y),
+        p.z.max(min.z).min(max.z),
+    )
+}
+
+/// Are b and s touching?
 pub fn touching_box_sphere(b: &BBox, s: &Sphere) -> bool {
-    if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        return true;
-    } else if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        return true;
-    } else if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        return true;
-    } else {
-        return true;
-    }
+    // Is the nearest part of the box within the sphere's radius of its center?
+    closest_point_box(b, s.c).distance2(s.c) <= s.r.powi(2)
 }
 
 pub fn disp_box_box(b1: &BBox, b2: &BBox) -> Option<Vec3> {
     let mut x_overlap = 0.0;
     let mut y_overlap = 0.0;
     let mut z_overlap = 0.0;
-    if !((b1.halfwidth + b2.halfwidth) < (b1.center.x - b2.center.x).abs()) {
-        x_overlap = (b1.halfwidth + b2.halfwidth) - (b1.center.x - b2.center.x).abs();
+    if !((b1.half_extents.x + b2.half_extents.x) < (b1.center.x - b2.center.x).abs()) {
+        x_overlap = (b1.half_extents.x + b2.half_extents.x) - (b1.center.x - b2.center.x).abs();
         if b1.center.x < b2.center.x {
             x_overlap = x_overlap * -1.0;
         }
     }
-    if !((b1.halfwidth + b2.halfwidth) < (b1.center.y - b2.center.y).abs()) {
-        y_overlap = (b1.halfwidth + b2.halfwidth) - (b1.center.y - b2.center.y).abs();
+    if !((b1.half_extents.y + b2.half_extents.y) < (b1.center.y - b2.center.y).abs()) {
+        y_overlap = (b1.half_extents.y + b2.half_extents.y) - (b1.center.y - b2.center.y).abs();
         if b1.center.y < b2.center.y {
             y_overlap = y_overlap * -1.0;
         }
     }
-    if !((b1.halfwidth + b2.halfwidth).abs() < (b1.center.z - b2.center.z).abs()) {
-        z_overlap = (b1.halfwidth + b2.halfwidth) - (b1.center.z - b2.center.z).abs();
+    if !((b1.half_extents.z + b2.half_extents.z).abs() < (b1.center.z - b2.center.z).abs()) {
+        z_overlap = (b1.half_extents.z + b2.half_extents.z) - (b1.center.z - b2.center.z).abs();
         if b1.center.z < b2.center.z {
             z_overlap = z_overlap * -1.0;
         }
@@ -82,23 +87,33 @@ pub fn disp_box_box(b1: &BBox, b2: &BBox) -> Option<Vec3> {
     }
 }
 
+/// What's the offset I'd need to push s out of b?
 pub fn disp_box_sphere(b: &BBox, s: &Sphere) -> Option<Vec3> {
-    let mut x_overlap = 0.0;
-    let mut y_overlap = 0.0;
-    let mut z_overlap = 0.0;
-    if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        x_overlap = (s.r + b.halfwidth) - (s.c.x - b.center.x).abs()
-    }
-    if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        y_overlap = (s.r + b.halfwidth) - (s.c.y - b.center.y).abs()
-    }
-    if s.c.distance2(b.center) <= (s.r + b.halfwidth).powi(2) {
-        z_overlap = (s.r + b.halfwidth) - (s.c.z - b.center.z).abs()
-    }
-    if x_overlap == 0.0 && y_overlap == 0.0 && z_overlap == 0.0 {
-        None
+    let offset = s.c - closest_point_box(b, s.c);
+    let distance = offset.magnitude();
+    if distance > 0.0 {
+        // The center is outside of the box, so push it straight away from the nearest point
+        if distance < s.r {
+            Some(offset * ((s.r - distance) / distance))
+        } else {
+            None
+        }
     } else {
-        Some(Vec3::new(x_overlap / 2.0, y_overlap / 2.0, z_overlap / 2.0)) // SOMETHING FUNNY IS GOING ON (DIVIDE BY 2 MAYBE?)
+        // The center is inside of the box, so push it out through the nearest face
+        let from_center = s.c - b.center;
+        let mut disp = Vec3::zero();
+        let mut shortest = f32::INFINITY;
+        for axis in 0..3 {
+            for &side in &[-1.0, 1.0] {
+                let depth = b.half_extents[axis] - side * from_center[axis];
+                if depth < shortest {
+                    shortest = depth;
+                    disp = Vec3::zero();
+                    disp[axis] = side * (depth + s.r);
+                }
+            }
+        }
+        Some(disp)
     }
 }
 
@@ -141,14 +156,28 @@ pub fn disp_sphere_plane(s: &Sphere, p: &Plane) -> Option<Vec3> {
     }
 }
 
+/// How far b reaches out from its center along the plane's normal
+fn box_reach(b: &BBox, p: &Plane) -> f32 {
+    b.half_extents.x * p.n.x.abs()
+        + b.half_extents.y * p.n.y.abs()
+        + b.half_extents.z * p.n.z.abs()
+}
+
+/// Is any part of b on the plane?
+pub fn touching_box_plane(b: &BBox, p: &Plane) -> bool {
+    // Find the distance of the box's center to the plane
+    (b.center.dot(p.n) - p.d).abs() <= box_reach(b, p)
+}
+
 pub fn disp_box_plane(b: &BBox, p: &Plane) -> Option<Vec3> {
-    // Find the distance of the sphere's center to the plane
+    // Find the distance of the box's center to the plane
     let dist = b.center.dot(p.n) - p.d;
-    if dist.abs() <= b.halfwidth {
-        // If we offset from the sphere position opposite the normal,
-        // we'll end up hitting the plane at `dist` units away.  So
-        // the displacement is just the plane's normal * dist.
-        return Some(p.n * (b.halfwidth - dist) * 1.03);
+    let reach = box_reach(b, p);
+    if dist.abs() <= reach {
+        // Push the box along the normal until its farthest corner behind
+        // the plane is just in front of it (plus a little, so it doesn't
+        // stay touching).
+        return Some(p.n * (reach - dist) * 1.03);
     } else {
         None
     }
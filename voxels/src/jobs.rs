@@ -0,0 +1,121 @@
+use crate::blocks::BlockRegistry;
+use crate::light::{light_alone, LightChunk};
+use crate::mesher::{chunk_mesh, ChunkMesh, Neighborhood};
+use crate::voxel::Chunk;
+use crate::world::ChunkCoord;
+use crate::world_gen::WorldGen;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// How far along a chunk is in being loaded
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkState {
+    Unloaded,
+    Generating, // Waiting on a worker to build its terrain
+    Generated,  // Terrain is in the world, but there is nothing to draw yet
+    Meshing,    // Waiting on a worker to build its mesh
+    Ready,      // Terrain is loaded and being drawn
+}
+
+impl ChunkState {
+    /// Is the chunk's voxel data in the world?
+    pub fn is_loaded(&self) -> bool {
+        matches!(
+            self,
+            ChunkState::Generated | ChunkState::Meshing | ChunkState::Ready
+        )
+    }
+}
+
+// Every job is tagged with the epoch of the world that asked for it,
+// so results for a world that has since been replaced can be thrown away.
+// Mesh jobs also carry a number that goes up with every mesh asked for,
+// so only the newest mesh of a chunk gets drawn
+pub(crate) enum Job {
+    Generate(u64, ChunkCoord, Arc<WorldGen>),
+    Mesh(u64, ChunkCoord, u64, Box<Neighborhood>, Arc<BlockRegistry>),
+}
+
+pub(crate) enum JobResult {
+    Generated(u64, ChunkCoord, Box<Chunk>, Box<LightChunk>),
+    Meshed(u64, ChunkCoord, u64, ChunkMesh),
+}
+
+/// Pool of worker threads that generate, light and mesh chunks off of the main thread
+pub(crate) struct JobPool {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl JobPool {
+    pub(crate) fn new(worker_count: usize) -> Self {
+        let (job_sender, job_receiver) = channel::<Job>();
+        let (result_sender, results) = channel();
+        // The workers take turns pulling jobs off of a single queue
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                std::thread::Builder::new()
+                    .name(format!("chunk worker {}", i))
+                    .spawn(move || worker(job_receiver, result_sender))
+                    .expect("failed to spawn chunk worker")
+            })
+            .collect();
+        Self {
+            jobs: Some(job_sender),
+            results,
+            workers,
+        }
+    }
+
+    pub(crate) fn submit(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            // Only fails if every worker is gone, in which case there is nobody to do the job anyway
+            let _ = jobs.send(job);
+        }
+    }
+
+    /// Every result that has finished since the last call, without blocking
+    pub(crate) fn finished(&self) -> impl Iterator<Item = JobResult> + '_ {
+        self.results.try_iter()
+    }
+}
+
+impl Drop for JobPool {
+    fn drop(&mut self) {
+        // Closing the channel makes the workers stop once the queue is empty
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(jobs: Arc<Mutex<Receiver<Job>>>, results: Sender<JobResult>) {
+    loop {
+        let job = match jobs.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let result = match job {
+            Ok(Job::Generate(epoch, coord, generator)) => {
+                let chunk = generator.generate_chunk(coord);
+                let light = light_alone(&chunk, generator.blocks(), coord, generator.sky_layer());
+                JobResult::Generated(epoch, coord, Box::new(chunk), Box::new(light))
+            }
+            Ok(Job::Mesh(epoch, coord, generation, neighborhood, blocks)) => {
+                let mesh = chunk_mesh(&blocks, &neighborhood, coord);
+                JobResult::Meshed(epoch, coord, generation, mesh)
+            }
+            // The pool was dropped
+            Err(_) => return,
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
@@ -8,21 +8,27 @@ use winit::{
 pub mod camera;
 pub mod camera_control;
 pub mod collision;
-pub mod coordinates;
 pub mod events;
 pub mod geom;
-pub mod instance_raw;
+pub mod jobs;
+pub mod light;
+pub mod mesher;
 pub mod model;
 pub mod particle;
 pub mod player;
 pub mod texture;
 pub mod voxel;
+pub mod world;
 use events::Events;
 pub mod render;
 use render::Render;
 pub mod assets;
+pub mod blocks;
+pub mod breaking;
 pub mod save;
 pub mod sound;
+pub mod streaming;
+pub mod text;
 pub mod world_gen;
 use sound::Sound;
 
@@ -0,0 +1,451 @@
+use crate::blocks::BlockRegistry;
+use crate::mesher::Face;
+use crate::voxel::*;
+use crate::world::{ChunkCoord, VoxelCoord, World};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Brightest a voxel can be. Light gets one level dimmer for every voxel it travels through
+pub const MAX_LIGHT: u8 = 15;
+
+/// Position of a voxel counting from the world origin, in voxels
+pub type VoxelPos = [i32; 3];
+
+/// The two kinds of light a voxel can hold. Sky light comes down from above the terrain and
+/// travels straight down without dimming, block light comes from blocks with an emission
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    const ALL: [Channel; 2] = [Channel::Sky, Channel::Block];
+}
+
+/// Light levels for every voxel in a chunk, with sky light in the high four bits and block light in the low four
+#[derive(Clone)]
+pub struct LightChunk {
+    pub data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+}
+
+impl Default for LightChunk {
+    fn default() -> Self {
+        Self {
+            data: [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+        }
+    }
+}
+
+impl LightChunk {
+    pub fn sky(&self, (x, y, z): VoxelCoord) -> u8 {
+        self.data[x][y][z] >> 4
+    }
+
+    pub fn block(&self, (x, y, z): VoxelCoord) -> u8 {
+        self.data[x][y][z] & 0xF
+    }
+
+    pub fn get(&self, voxel: VoxelCoord, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(voxel),
+            Channel::Block => self.block(voxel),
+        }
+    }
+
+    fn set(&mut self, (x, y, z): VoxelCoord, channel: Channel, level: u8) {
+        let packed = &mut self.data[x][y][z];
+        *packed = match channel {
+            Channel::Sky => (*packed & 0xF) | (level << 4),
+            Channel::Block => (*packed & 0xF0) | level,
+        };
+    }
+}
+
+/// Light for every loaded chunk. It's flood filled outwards from light sources through transparent
+/// blocks when a chunk is loaded, and patched up around a voxel whenever one changes
+pub struct Lighting {
+    chunks: HashMap<ChunkCoord, LightChunk>,
+    // Chunks whose light changed since the last call to take_relit, so they can be meshed again
+    relit: HashSet<ChunkCoord>,
+    // Chunk layers from here up are open to the sky when they aren't loaded
+    sky_layer: i32,
+}
+
+impl Lighting {
+    pub fn new(sky_layer: i32) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            relit: HashSet::new(),
+            sky_layer,
+        }
+    }
+
+    pub fn get(&self, coord: ChunkCoord) -> Option<&LightChunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn remove(&mut self, coord: ChunkCoord) {
+        self.chunks.remove(&coord);
+        self.relit.remove(&coord);
+    }
+
+    pub fn retain<F: Fn(ChunkCoord) -> bool>(&mut self, keep: F) {
+        self.chunks.retain(|coord, _| keep(*coord));
+        self.relit.retain(|coord| keep(*coord));
+    }
+
+    /// Chunks whose light changed since this was last called
+    pub fn take_relit(&mut self) -> HashSet<ChunkCoord> {
+        std::mem::take(&mut self.relit)
+    }
+
+    /// Lights a chunk that was just loaded, pulling in light from the chunks around it and
+    /// spreading its own light out into them
+    pub fn light_chunk(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        coord: ChunkCoord,
+    ) {
+        let chunk = match chunks.get(&coord) {
+            Some(chunk) => chunk,
+            None => return,
+        };
+        self.chunks.insert(coord, LightChunk::default());
+        self.relit.insert(coord);
+
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+        let origin = [
+            coord.0 * CHUNK_SIZE as i32,
+            coord.1 * CHUNK_SIZE as i32,
+            coord.2 * CHUNK_SIZE as i32,
+        ];
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let pos = [
+                        origin[0] + x as i32,
+                        origin[1] + y as i32,
+                        origin[2] + z as i32,
+                    ];
+                    let voxel = blocks.get(chunk.data[x][y][z]);
+                    if voxel.emission > 0 {
+                        self.set(pos, Channel::Block, voxel.emission);
+                        block.push_back(pos);
+                    }
+                    if voxel.transparent && self.under_open_sky(chunks, pos) {
+                        self.set(pos, Channel::Sky, MAX_LIGHT);
+                        sky.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        // Light already in the neighbours shines in through the chunk's sides
+        for face in Face::ALL.iter().copied() {
+            if !chunks.contains_key(&World::neighbor_coord(coord, face)) {
+                continue;
+            }
+            let axis = face.axis();
+            // Just outside of the chunk, in the neighbour
+            let border = if face.is_positive() {
+                CHUNK_SIZE as i32
+            } else {
+                -1
+            };
+            for i in 0..CHUNK_SIZE as i32 {
+                for j in 0..CHUNK_SIZE as i32 {
+                    let mut pos = [0; 3];
+                    pos[axis] = border;
+                    pos[(axis + 1) % 3] = i;
+                    pos[(axis + 2) % 3] = j;
+                    let pos = [origin[0] + pos[0], origin[1] + pos[1], origin[2] + pos[2]];
+                    sky.push_back(pos);
+                    block.push_back(pos);
+                }
+            }
+        }
+
+        self.spread(chunks, blocks, Channel::Sky, sky);
+        // The chunk below might have been lit as if it were open to the sky
+        let refill = self.cover(chunks, blocks, World::neighbor_coord(coord, Face::NegY));
+        self.spread(chunks, blocks, Channel::Sky, refill);
+        self.spread(chunks, blocks, Channel::Block, block);
+    }
+
+    /// Adds a chunk that was lit by light_alone, then joins its light up with the chunks around it.
+    /// Ends up the same as light_chunk, but most of the work has already been done
+    pub fn add_lit_chunk(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        coord: ChunkCoord,
+        light: LightChunk,
+    ) {
+        if !chunks.contains_key(&coord) {
+            return;
+        }
+        self.chunks.insert(coord, light);
+        self.relit.insert(coord);
+        for face in Face::ALL.iter().copied() {
+            self.relit.insert(World::neighbor_coord(coord, face));
+        }
+        let origin = [
+            coord.0 * CHUNK_SIZE as i32,
+            coord.1 * CHUNK_SIZE as i32,
+            coord.2 * CHUNK_SIZE as i32,
+        ];
+
+        // light_alone lit the top from the sky, but a chunk above can be in the way of some of it,
+        // and this chunk can be in the way of the sky for the chunk below
+        let mut sky = self.cover(chunks, blocks, coord);
+        sky.extend(self.cover(chunks, blocks, World::neighbor_coord(coord, Face::NegY)));
+
+        // Light on either side of each face shines across into the other chunk
+        let mut block = VecDeque::new();
+        for face in Face::ALL.iter().copied() {
+            if !chunks.contains_key(&World::neighbor_coord(coord, face)) {
+                continue;
+            }
+            let axis = face.axis();
+            let (inside, outside) = if face.is_positive() {
+                (CHUNK_SIZE as i32 - 1, CHUNK_SIZE as i32)
+            } else {
+                (0, -1)
+            };
+            for i in 0..CHUNK_SIZE as i32 {
+                for j in 0..CHUNK_SIZE as i32 {
+                    for layer in [inside, outside].iter() {
+                        let mut pos = [0; 3];
+                        pos[axis] = *layer;
+                        pos[(axis + 1) % 3] = i;
+                        pos[(axis + 2) % 3] = j;
+                        let pos = [origin[0] + pos[0], origin[1] + pos[1], origin[2] + pos[2]];
+                        sky.push_back(pos);
+                        block.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        self.spread(chunks, blocks, Channel::Sky, sky);
+        self.spread(chunks, blocks, Channel::Block, block);
+    }
+
+    /// Updates the light around a voxel that was just changed
+    pub fn update_voxel(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        pos: VoxelPos,
+    ) {
+        let voxel = match voxel_at(chunks, pos) {
+            Some(voxel) => blocks.get(voxel),
+            None => return,
+        };
+        for channel in Channel::ALL.iter().copied() {
+            // Take away whatever light passed through the voxel, then fill the gap back in from
+            // whatever is still lit around it
+            let old = self.level(pos, channel).unwrap_or(0);
+            let mut refill = VecDeque::new();
+            if old > 0 {
+                self.set(pos, channel, 0);
+                let mut removed = VecDeque::new();
+                removed.push_back((pos, old));
+                refill = self.unspread(chunks, blocks, channel, removed);
+            }
+            if voxel.transparent {
+                refill.extend(Face::ALL.iter().map(|face| step(pos, *face)));
+                if channel == Channel::Sky && self.under_open_sky(chunks, pos) {
+                    self.set(pos, channel, MAX_LIGHT);
+                    refill.push_back(pos);
+                }
+            }
+            if channel == Channel::Block && voxel.emission > 0 {
+                self.set(pos, channel, voxel.emission);
+                refill.push_back(pos);
+            }
+            self.spread(chunks, blocks, channel, refill);
+        }
+    }
+
+    /// Light level of a voxel. None if its chunk isn't lit
+    pub fn level(&self, pos: VoxelPos, channel: Channel) -> Option<u8> {
+        let (coord, voxel) = split(pos);
+        self.chunks
+            .get(&coord)
+            .map(|light| light.get(voxel, channel))
+    }
+
+    fn set(&mut self, pos: VoxelPos, channel: Channel, level: u8) {
+        let (coord, voxel) = split(pos);
+        if let Some(light) = self.chunks.get_mut(&coord) {
+            light.set(voxel, channel, level);
+            self.relit.insert(coord);
+            // Faces in the chunks next door get their light from voxels on the border
+            let voxel = [voxel.0, voxel.1, voxel.2];
+            for face in Face::ALL.iter().copied() {
+                let border = if face.is_positive() {
+                    CHUNK_SIZE - 1
+                } else {
+                    0
+                };
+                if voxel[face.axis()] == border {
+                    self.relit.insert(World::neighbor_coord(coord, face));
+                }
+            }
+        }
+    }
+
+    /// Is the voxel at the top of its chunk with nothing but unloaded sky above it?
+    fn under_open_sky(&self, chunks: &HashMap<ChunkCoord, Chunk>, pos: VoxelPos) -> bool {
+        let (coord, (_, y, _)) = split(pos);
+        let above = World::neighbor_coord(coord, Face::PosY);
+        y == CHUNK_SIZE - 1 && above.1 >= self.sky_layer && !chunks.contains_key(&above)
+    }
+
+    /// Flood fills light outwards from every lit voxel in the queue
+    fn spread(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        channel: Channel,
+        mut queue: VecDeque<VoxelPos>,
+    ) {
+        while let Some(pos) = queue.pop_front() {
+            let level = match self.level(pos, channel) {
+                Some(level) if level > 1 => level,
+                _ => continue,
+            };
+            for face in Face::ALL.iter().copied() {
+                let next = step(pos, face);
+                match voxel_at(chunks, next) {
+                    Some(voxel) if blocks.get(voxel).transparent => {}
+                    _ => continue,
+                }
+                let next_level =
+                    if channel == Channel::Sky && face == Face::NegY && level == MAX_LIGHT {
+                        MAX_LIGHT
+                    } else {
+                        level - 1
+                    };
+                if self.level(next, channel).unwrap_or(MAX_LIGHT) < next_level {
+                    self.set(next, channel, next_level);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Takes the sky light back out of the top of a chunk that was lit while the chunk above it wasn't
+    /// loaded, wherever that chunk turns out to be in the way. Returns the lit voxels that need to
+    /// spread their light back into the darkened area
+    fn cover(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        coord: ChunkCoord,
+    ) -> VecDeque<VoxelPos> {
+        let above = World::neighbor_coord(coord, Face::PosY);
+        if above.1 < self.sky_layer
+            || !chunks.contains_key(&above)
+            || !self.chunks.contains_key(&coord)
+        {
+            return VecDeque::new();
+        }
+        let mut removed = VecDeque::new();
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let pos = [
+                    coord.0 * CHUNK_SIZE as i32 + x,
+                    coord.1 * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 - 1,
+                    coord.2 * CHUNK_SIZE as i32 + z,
+                ];
+                let open = self.level(step(pos, Face::PosY), Channel::Sky) == Some(MAX_LIGHT);
+                if self.level(pos, Channel::Sky) == Some(MAX_LIGHT) && !open {
+                    self.set(pos, Channel::Sky, 0);
+                    removed.push_back((pos, MAX_LIGHT));
+                }
+            }
+        }
+        self.unspread(chunks, blocks, Channel::Sky, removed)
+    }
+
+    /// Darkens every voxel that was lit by the voxels in the queue (which have already been darkened,
+    /// and come with the level they used to have). Returns the lit voxels around the darkened area,
+    /// which need to spread their light back into it
+    fn unspread(
+        &mut self,
+        chunks: &HashMap<ChunkCoord, Chunk>,
+        blocks: &BlockRegistry,
+        channel: Channel,
+        mut queue: VecDeque<(VoxelPos, u8)>,
+    ) -> VecDeque<VoxelPos> {
+        let mut refill = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for face in Face::ALL.iter().copied() {
+                let next = step(pos, face);
+                let next_level = match self.level(next, channel) {
+                    Some(next_level) if next_level > 0 => next_level,
+                    _ => continue,
+                };
+                let lit_from_here = next_level < level
+                    || (channel == Channel::Sky && face == Face::NegY && level == MAX_LIGHT);
+                if !lit_from_here {
+                    refill.push_back(next);
+                    continue;
+                }
+                self.set(next, channel, 0);
+                queue.push_back((next, next_level));
+                // Emissive voxels keep their own light
+                let glow = voxel_at(chunks, next).map_or(0, |voxel| blocks.get(voxel).emission);
+                if channel == Channel::Block && glow > 0 {
+                    self.set(next, channel, glow);
+                    refill.push_back(next);
+                }
+            }
+        }
+        refill
+    }
+}
+
+/// Lights a chunk as if nothing around it were loaded, with its top open to the sky if the chunk
+/// above it is at or above the sky layer. This is the slow part of lighting a new chunk and doesn't
+/// need the rest of the world, so it can be done off of the main thread and handed to
+/// Lighting::add_lit_chunk
+pub fn light_alone(
+    chunk: &Chunk,
+    blocks: &BlockRegistry,
+    coord: ChunkCoord,
+    sky_layer: i32,
+) -> LightChunk {
+    let mut chunks = HashMap::new();
+    chunks.insert(coord, chunk.clone());
+    let mut lighting = Lighting::new(sky_layer);
+    lighting.light_chunk(&chunks, blocks, coord);
+    lighting.chunks.remove(&coord).unwrap_or_default()
+}
+
+/// Splits a voxel position into its chunk and the voxel inside of that chunk
+pub fn split([x, y, z]: VoxelPos) -> (ChunkCoord, VoxelCoord) {
+    let size = CHUNK_SIZE as i32;
+    (
+        (x.div_euclid(size), y.div_euclid(size), z.div_euclid(size)),
+        (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        ),
+    )
+}
+
+fn step([x, y, z]: VoxelPos, face: Face) -> VoxelPos {
+    let (dx, dy, dz) = face.normal();
+    [x + dx, y + dy, z + dz]
+}
+
+fn voxel_at(chunks: &HashMap<ChunkCoord, Chunk>, pos: VoxelPos) -> Option<u8> {
+    let (coord, (x, y, z)) = split(pos);
+    chunks.get(&coord).map(|chunk| chunk.data[x][y][z])
+}
@@ -0,0 +1,495 @@
+use crate::blocks::{BlockRegistry, RenderMode, AIR};
+use crate::light::{LightChunk, MAX_LIGHT};
+use crate::model::VoxelVertex;
+use crate::voxel::*;
+use crate::world::{ChunkCoord, VoxelCoord, World};
+
+/// The six directions a voxel face can point in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    pub fn normal(self) -> (i32, i32, i32) {
+        match self {
+            Face::PosX => (1, 0, 0),
+            Face::NegX => (-1, 0, 0),
+            Face::PosY => (0, 1, 0),
+            Face::NegY => (0, -1, 0),
+            Face::PosZ => (0, 0, 1),
+            Face::NegZ => (0, 0, -1),
+        }
+    }
+
+    /// The axis the face points along (0 = x, 1 = y, 2 = z)
+    pub fn axis(self) -> usize {
+        match self {
+            Face::PosX | Face::NegX => 0,
+            Face::PosY | Face::NegY => 1,
+            Face::PosZ | Face::NegZ => 2,
+        }
+    }
+
+    /// Position of the face in Face::ALL
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn is_positive(self) -> bool {
+        matches!(self, Face::PosX | Face::PosY | Face::PosZ)
+    }
+
+    // Block textures are laid out like cube.obj's uvs, in a 3 by 3 grid with one face per cell
+    fn tex_cell(self) -> [f32; 2] {
+        let third = 1.0 / 3.0;
+        match self {
+            Face::PosX => [0.0, 0.0],
+            Face::NegX => [0.0, 2.0 * third],
+            Face::PosY => [2.0 * third, 0.0],
+            Face::NegY => [2.0 * third, third],
+            Face::PosZ => [third, 0.0],
+            Face::NegZ => [0.0, third],
+        }
+    }
+}
+
+/// A rectangle of faces that all point the same way and have the same voxel type
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Quad {
+    pub face: Face,
+    pub voxel: u8,
+    pub origin: [usize; 3], // Voxel in the corner of the quad with the smallest coordinates
+    pub size: [usize; 2], // How many voxels the quad covers along its first and second axis (see quad_axes)
+    pub ao: [u8; 4], // Ambient occlusion at the corners (0 = darkest, 3 = open), counter clockwise from the origin
+    pub light: [u8; 4], // Light at the corners in the same order, packed like LightChunk's data
+}
+
+/// The two axes a face's quads lie along, picked so that first x second points along +axis
+pub fn quad_axes(face: Face) -> (usize, usize) {
+    let axis = face.axis();
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+/// Copies of a chunk and the six chunks touching it (in Face::ALL order, None where they
+/// aren't loaded) along with their light, so that it can be meshed on another thread
+#[derive(Clone)]
+pub struct Neighborhood {
+    pub chunk: Chunk,
+    pub neighbors: [Option<Chunk>; 6],
+    pub light: LightChunk,
+    pub neighbor_light: [Option<LightChunk>; 6],
+}
+
+impl Neighborhood {
+    pub fn neighbors(&self) -> [Option<&Chunk>; 6] {
+        let mut neighbors = [None; 6];
+        for (neighbor, chunk) in neighbors.iter_mut().zip(self.neighbors.iter()) {
+            *neighbor = chunk.as_ref();
+        }
+        neighbors
+    }
+
+    pub fn lights(&self) -> Lights<'_> {
+        let mut neighbors = [None; 6];
+        for (neighbor, light) in neighbors.iter_mut().zip(self.neighbor_light.iter()) {
+            *neighbor = light.as_ref();
+        }
+        Lights {
+            chunk: &self.light,
+            neighbors,
+        }
+    }
+}
+
+/// Light for a chunk and its neighbours (in Face::ALL order), to go along with the voxels given to greedy_mesh
+pub struct Lights<'a> {
+    pub chunk: &'a LightChunk,
+    pub neighbors: [Option<&'a LightChunk>; 6],
+}
+
+/// Merges the visible faces of a chunk into as few quads as it can. Faces are only merged with
+/// faces pointing the same way with the same voxel type, ambient occlusion and light, so each quad can still be
+/// drawn with one texture and smooth shading. A face is visible when the block in front of it is transparent,
+/// unless it's the same translucent block (so there are no faces inside of water or glass).
+/// Faces on the border of the chunk are checked against the neighbouring chunks (in Face::ALL order),
+/// and are visible if that neighbour isn't loaded. Without any lights every face is fully lit
+pub fn greedy_mesh(
+    blocks: &BlockRegistry,
+    chunk: &Chunk,
+    neighbors: &[Option<&Chunk>; 6],
+    lights: Option<&Lights>,
+) -> Vec<Quad> {
+    let lookup = Lookup {
+        blocks,
+        chunk,
+        neighbors,
+        lights,
+    };
+    let mut quads = vec![];
+    for face in Face::ALL.iter().copied() {
+        let axis = face.axis();
+        let (u, v) = quad_axes(face);
+        let (nx, ny, nz) = face.normal();
+        for slice in 0..CHUNK_SIZE {
+            // Voxel type, occlusion and light of every visible face in this slice, indexed by [u][v]
+            let mut mask = [[(0u8, [0u8; 4], [0u8; 4]); CHUNK_SIZE]; CHUNK_SIZE];
+            for (i, row) in mask.iter_mut().enumerate() {
+                for (j, m) in row.iter_mut().enumerate() {
+                    let mut pos = [0; 3];
+                    pos[axis] = slice;
+                    pos[u] = i;
+                    pos[v] = j;
+                    let voxel = chunk.data[pos[0]][pos[1]][pos[2]];
+                    let front = [pos[0] as i32 + nx, pos[1] as i32 + ny, pos[2] as i32 + nz];
+                    let textured = blocks.face_texture(voxel, face).is_some();
+                    if textured && !lookup.hides(voxel, front) {
+                        let (ao, light) = corner_shading(&lookup, front, u, v);
+                        *m = (voxel, ao, light);
+                    }
+                }
+            }
+
+            for i in 0..CHUNK_SIZE {
+                let mut j = 0;
+                while j < CHUNK_SIZE {
+                    let key = mask[i][j];
+                    if key.0 == AIR {
+                        j += 1;
+                        continue;
+                    }
+                    // Grow along v as far as the faces match, then along u while every row matches
+                    let mut height = 1;
+                    while j + height < CHUNK_SIZE && mask[i][j + height] == key {
+                        height += 1;
+                    }
+                    let mut width = 1;
+                    while i + width < CHUNK_SIZE
+                        && mask[i + width][j..j + height].iter().all(|m| *m == key)
+                    {
+                        width += 1;
+                    }
+                    for row in mask.iter_mut().skip(i).take(width) {
+                        for m in row.iter_mut().skip(j).take(height) {
+                            m.0 = AIR;
+                        }
+                    }
+
+                    let mut origin = [0; 3];
+                    origin[axis] = slice;
+                    origin[u] = i;
+                    origin[v] = j;
+                    quads.push(Quad {
+                        face,
+                        voxel: key.0,
+                        origin,
+                        size: [width, height],
+                        ao: key.1,
+                        light: key.2,
+                    });
+                    j += height;
+                }
+            }
+        }
+    }
+    quads
+}
+
+/// Ambient occlusion (0 = darkest, 3 = open) and light at each corner of a face, in the same order as Quad::ao.
+/// front is the voxel the face looks into, and u and v are the face's quad axes
+fn corner_shading(lookup: &Lookup, front: [i32; 3], u: usize, v: usize) -> ([u8; 4], [u8; 4]) {
+    let mut ao = [0; 4];
+    let mut light = [0; 4];
+    for ((corner_ao, corner_light), (cu, cv)) in
+        ao.iter_mut().zip(light.iter_mut()).zip(QUAD_CORNERS.iter())
+    {
+        let mut side_u = front;
+        side_u[u] += *cu as i32 * 2 - 1;
+        let mut side_v = front;
+        side_v[v] += *cv as i32 * 2 - 1;
+        let mut diagonal = side_u;
+        diagonal[v] = side_v[v];
+        let (opaque_u, opaque_v) = (lookup.is_opaque(side_u), lookup.is_opaque(side_v));
+        // The diagonal can't be seen past two opaque sides
+        let opaque_diagonal = (opaque_u && opaque_v) || lookup.is_opaque(diagonal);
+        *corner_ao = if opaque_u && opaque_v {
+            0
+        } else {
+            3 - opaque_u as u8 - opaque_v as u8 - opaque_diagonal as u8
+        };
+
+        // Average the light of the open voxels around the corner, so it fades smoothly across faces
+        let (mut sky, mut block, mut count) = (0, 0, 0);
+        for (pos, opaque) in [
+            (front, false),
+            (side_u, opaque_u),
+            (side_v, opaque_v),
+            (diagonal, opaque_diagonal),
+        ]
+        .iter()
+        {
+            if let (false, Some(level)) = (*opaque, lookup.light(*pos)) {
+                sky += level as u32 >> 4;
+                block += level as u32 & 0xF;
+                count += 1;
+            }
+        }
+        let average = |sum: u32| (sum + count / 2).checked_div(count);
+        *corner_light = match (average(sky), average(block)) {
+            (Some(sky), Some(block)) => ((sky << 4) | block) as u8,
+            _ => UNKNOWN_LIGHT,
+        };
+    }
+    (ao, light)
+}
+
+// Quad corners as (u, v), counter clockwise when looking at a positive face
+const QUAD_CORNERS: [(usize, usize); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+// Light used where the light isn't known, as if the face was out under the sky
+const UNKNOWN_LIGHT: u8 = MAX_LIGHT << 4;
+// Light used when meshing without any lights
+const FULL_LIGHT: u8 = (MAX_LIGHT << 4) | MAX_LIGHT;
+
+/// Looks up voxels and light in a chunk and the chunks next to it
+struct Lookup<'a> {
+    blocks: &'a BlockRegistry,
+    chunk: &'a Chunk,
+    neighbors: &'a [Option<&'a Chunk>; 6],
+    lights: Option<&'a Lights<'a>>,
+}
+
+impl<'a> Lookup<'a> {
+    /// Finds the chunk that pos is in (None for the chunk itself, or the index of the neighbour) and where
+    /// it is in that chunk. pos is relative to the chunk and can be up to a voxel outside of it.
+    /// Returns None for positions diagonally past the chunk's edges
+    fn locate(&self, [x, y, z]: [i32; 3]) -> Option<(Option<usize>, VoxelCoord)> {
+        let size = CHUNK_SIZE as i32;
+        let mut neighbor = None;
+        for (c, positive, negative) in [
+            (x, Face::PosX, Face::NegX),
+            (y, Face::PosY, Face::NegY),
+            (z, Face::PosZ, Face::NegZ),
+        ]
+        .iter()
+        {
+            let face = if *c >= size {
+                *positive
+            } else if *c < 0 {
+                *negative
+            } else {
+                continue;
+            };
+            if neighbor.is_some() {
+                return None;
+            }
+            neighbor = Some(face.index());
+        }
+        let voxel = (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        );
+        Some((neighbor, voxel))
+    }
+
+    /// The voxel at pos. Voxels in unloaded neighbours, or diagonally past the chunk's edges, count as empty
+    fn voxel(&self, pos: [i32; 3]) -> u8 {
+        let (chunk, (x, y, z)) = match self.locate(pos) {
+            Some((None, voxel)) => (self.chunk, voxel),
+            Some((Some(neighbor), voxel)) => match self.neighbors[neighbor] {
+                Some(chunk) => (chunk, voxel),
+                None => return AIR,
+            },
+            None => return AIR,
+        };
+        chunk.data[x][y][z]
+    }
+
+    /// Does the voxel at pos hide what's behind it?
+    fn is_opaque(&self, pos: [i32; 3]) -> bool {
+        !self.blocks.get(self.voxel(pos)).transparent
+    }
+
+    /// Does the voxel at pos hide the face of a voxel that's looking into it?
+    fn hides(&self, voxel: u8, pos: [i32; 3]) -> bool {
+        let front = self.voxel(pos);
+        self.is_opaque(pos)
+            || (front == voxel && self.blocks.get(voxel).render == RenderMode::Translucent)
+    }
+
+    /// Light at pos, packed like LightChunk's data. None where it isn't known
+    fn light(&self, pos: [i32; 3]) -> Option<u8> {
+        let lights = match self.lights {
+            Some(lights) => lights,
+            None => return Some(FULL_LIGHT),
+        };
+        let (light, (x, y, z)) = match self.locate(pos)? {
+            (None, voxel) => (lights.chunk, voxel),
+            (Some(neighbor), voxel) => (lights.neighbors[neighbor]?, voxel),
+        };
+        Some(light.data[x][y][z])
+    }
+}
+
+// How bright a vertex is for each level of ambient occlusion
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// Vertices and triangle indices for a set of faces, with four vertices and six indices per quad
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<VoxelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// The indices with the quads put in order from furthest to nearest to eye, so that
+    /// translucent faces get blended over the ones behind them
+    pub fn sorted_back_to_front(&self, eye: [f32; 3]) -> Vec<u32> {
+        let distance = |quad: &[u32]| {
+            let first = *quad.iter().min().unwrap() as usize;
+            let mut center = [0.0; 3];
+            for vertex in &self.vertices[first..first + 4] {
+                for (c, p) in center.iter_mut().zip(vertex.position.iter()) {
+                    *c += p / 4.0;
+                }
+            }
+            center
+                .iter()
+                .zip(eye.iter())
+                .map(|(c, e)| (c - e).powi(2))
+                .sum::<f32>()
+        };
+        let mut quads: Vec<(f32, &[u32])> = self
+            .indices
+            .chunks(6)
+            .map(|quad| (distance(quad), quad))
+            .collect();
+        quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        quads
+            .into_iter()
+            .flat_map(|(_, quad)| quad)
+            .copied()
+            .collect()
+    }
+}
+
+/// A chunk's faces, split up by how they get drawn
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkMesh {
+    pub opaque: MeshData,
+    pub cutout: MeshData,
+    pub translucent: MeshData,
+}
+
+impl ChunkMesh {
+    pub fn get_mut(&mut self, render: RenderMode) -> &mut MeshData {
+        match render {
+            RenderMode::Opaque => &mut self.opaque,
+            RenderMode::Cutout => &mut self.cutout,
+            RenderMode::Translucent => &mut self.translucent,
+        }
+    }
+}
+
+/// Builds the mesh for a chunk. Each face's vertices say which layer of the block texture array
+/// (see BlockRegistry::textures) it's drawn with, so the chunk only needs one mesh for each RenderMode.
+/// This doesn't touch the GPU, so it can run on a worker thread
+pub fn chunk_mesh(
+    blocks: &BlockRegistry,
+    neighborhood: &Neighborhood,
+    coord: ChunkCoord,
+) -> ChunkMesh {
+    let mut meshes = ChunkMesh::default();
+    let origin = World::chunk_to_world(coord);
+    let origin = [origin.x, origin.y, origin.z];
+    let voxel_size = VOXEL_HALFWIDTH * 2.0;
+
+    let lights = neighborhood.lights();
+    for quad in greedy_mesh(
+        blocks,
+        &neighborhood.chunk,
+        &neighborhood.neighbors(),
+        Some(&lights),
+    ) {
+        let layer = match blocks.face_texture(quad.voxel, quad.face) {
+            Some(texture) => texture as u32,
+            None => continue,
+        };
+        let mesh = meshes.get_mut(blocks.get(quad.voxel).render);
+        let (u, v) = quad_axes(quad.face);
+        let mut base = quad.origin;
+        if quad.face.is_positive() {
+            // The face is on the far side of the voxel
+            base[quad.face.axis()] += 1;
+        }
+        let mut du = [0; 3];
+        du[u] = quad.size[0];
+        let mut dv = [0; 3];
+        dv[v] = quad.size[1];
+        // Counter clockwise when looking at the front of the face
+        let mut corners = QUAD_CORNERS;
+        if !quad.face.is_positive() {
+            corners.reverse();
+            corners.rotate_right(1);
+        }
+
+        let first = mesh.vertices.len() as u32;
+        let mut ao = [0; 4];
+        for (vertex_ao, (cu, cv)) in ao.iter_mut().zip(corners.iter()) {
+            let mut corner = [0.0; 3];
+            for i in 0..3 {
+                corner[i] = (base[i] + du[i] * cu + dv[i] * cv) as f32;
+            }
+            let index = QUAD_CORNERS.iter().position(|c| c == &(*cu, *cv)).unwrap();
+            *vertex_ao = quad.ao[index];
+            let light = quad.light[index];
+            mesh.vertices.push(VoxelVertex {
+                position: [
+                    origin[0] + corner[0] * voxel_size,
+                    origin[1] + corner[1] * voxel_size,
+                    origin[2] + corner[2] * voxel_size,
+                ],
+                tex_coords: face_tex_coords(quad.face, corner),
+                tex_cell: quad.face.tex_cell(),
+                ao: AO_BRIGHTNESS[*vertex_ao as usize],
+                light: [
+                    (light >> 4) as f32 / MAX_LIGHT as f32,
+                    (light & 0xF) as f32 / MAX_LIGHT as f32,
+                ],
+                layer,
+            });
+        }
+        // Split the quad along the diagonal that keeps the occlusion from looking lopsided
+        let indices = if ao[0] as u32 + ao[2] as u32 > ao[1] as u32 + ao[3] as u32 {
+            [first + 1, first + 2, first + 3, first + 1, first + 3, first]
+        } else {
+            [first, first + 1, first + 2, first, first + 2, first + 3]
+        };
+        mesh.indices.extend_from_slice(&indices);
+    }
+    meshes
+}
+
+// Texture coordinates in voxels, with the texture's up pointing towards +y on the sides
+fn face_tex_coords(face: Face, [x, y, z]: [f32; 3]) -> [f32; 2] {
+    match face.axis() {
+        0 => [z, -y],
+        1 => [x, z],
+        _ => [x, -y],
+    }
+}
@@ -0,0 +1,8 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    // Dark and a little see-through, so it shows up on light blocks without hiding dark ones
+    f_color = vec4(0.0, 0.0, 0.0, 0.6);
+}
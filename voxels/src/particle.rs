@@ -1,7 +1,5 @@
 use super::DT;
-use crate::geom::{Mat4, Pos3, Sphere, Vec3};
-use crate::instance_raw::InstanceRaw;
-use cgmath::EuclideanSpace;
+use crate::geom::{Pos3, Sphere, Vec3};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Particle {
@@ -12,13 +10,6 @@ pub struct Particle {
 }
 
 impl Particle {
-    #[allow(dead_code)]
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (Mat4::from_translation(self.body.c.to_vec()) * Mat4::from_scale(self.body.r))
-                .into(),
-        }
-    }
     #[allow(dead_code)]
     fn update(&mut self, g: f32) {
         if self.visible {
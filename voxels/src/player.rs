@@ -1,185 +1,359 @@
 use crate::camera::Camera;
-use crate::collision::*;
 use crate::geom::*;
-use crate::voxel::*;
+use crate::world::World;
 use crate::Events;
+use serde::{Deserialize, Serialize};
 use winit::event::*;
 
+/// Everything about the player (and where they're looking) that gets saved with a world
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub facing_direction: [f32; 3],
+    pub do_gravity: bool,
+    pub hotbar_slot: u8,
+    pub look_direction: [f32; 3], // Camera target relative to the camera eye
+    pub camera_up: [f32; 3],
+}
+
+/// How the player moves. Distances are in world units (a voxel is 4 across) and times are in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerParams {
+    pub walk_speed: f32,   // Top speed along the ground, and of flying up and down
+    pub sprint_speed: f32, // Top speed while sprinting
+    pub crouch_speed: f32, // Top speed while crouching
+    pub acceleration: f32, // How quickly the player gets up to speed while a key is held
+    pub friction: f32,     // How quickly the player slows to a stop once the keys are let go
+    pub gravity: f32,      // Downwards acceleration
+    pub terminal_velocity: f32, // The fastest the player can fall
+    pub jump_speed: f32,   // Upwards speed at the start of a jump
+    pub air_control: f32, // How much of acceleration and friction still applies while off the ground, from 0.0 to 1.0
+    pub crouch_drop: f32, // How much shorter the hitbox is (and how much lower the eyes are) while crouching
+    pub sprint_fov: f32,  // Degrees the camera's field of view widens by while sprinting
+}
+
+// Seconds it takes the field of view to widen all the way when sprinting starts (or narrow when it stops)
+const SPRINT_FOV_TIME: f32 = 0.15;
+
+// How far at a time a crouching player is pulled back from walking off of a ledge
+const EDGE_STEP: f32 = 0.05;
+
+impl Default for PlayerParams {
+    fn default() -> Self {
+        Self {
+            walk_speed: 30.0,
+            sprint_speed: 39.0,
+            crouch_speed: 9.0,
+            acceleration: 300.0,
+            friction: 300.0,
+            gravity: 36.0,
+            terminal_velocity: 12.0,
+            jump_speed: 18.0,
+            air_control: 0.3,
+            crouch_drop: 1.2,
+            sprint_fov: 10.0,
+        }
+    }
+}
+
+/// Which movement keys are held down
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,   // Fly straight up
+    pub down: bool, // Fly straight down
+    pub jump: bool,
+    pub sprint: bool, // Only while moving forward
+    pub crouch: bool,
+    pub toggle_gravity: bool, // Only true on the update the key went down
+}
+
+impl PlayerInput {
+    /// Which way the held keys point along the ground, given the way the camera is looking. Always
+    /// of length 1 (so walking diagonally is no faster than straight), or 0 if no keys are held or
+    /// they cancel out
+    pub fn wish_direction(&self, look: Vec3) -> Vec3 {
+        let forward = Vec3::new(look.x, 0.0, look.z);
+        if forward == Vec3::zero() {
+            // Looking straight up or down
+            return forward;
+        }
+        let forward = forward.normalize();
+        let right = forward.cross(Vec3::unit_y());
+        let mut wish = Vec3::zero();
+        if self.forward {
+            wish += forward;
+        }
+        if self.backward {
+            wish -= forward;
+        }
+        if self.right {
+            wish += right;
+        }
+        if self.left {
+            wish -= right;
+        }
+        if wish.magnitude2() > 1e-6 {
+            wish.normalize()
+        } else {
+            Vec3::zero()
+        }
+    }
+}
+
 pub struct Player {
     pub hitbox: BBox,
-    pub vx: f32,
+    pub eye_height: f32, // How far above the bottom of the hitbox the camera sits
+    pub vx: f32,         // Velocity, in world units per second
     pub vy: f32,
     pub vz: f32,
     pub facing_direction: Vec3,
-    pub speed: f32,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool,
-    is_up_pressed: bool,
-    is_down_pressed: bool,
-    is_gravity_pressed: bool,
+    pub params: PlayerParams,
+    pub input: PlayerInput,
     do_gravity: bool,
-    jump_pressed: bool,
     pub can_jump: bool,
-    pub x_pos_blocked: bool,
-    pub x_neg_blocked: bool,
-    pub y_pos_blocked: bool,
-    pub y_neg_blocked: bool,
-    pub z_pos_blocked: bool,
-    pub z_neg_blocked: bool,
+    crouching: bool,
+    sprinting: bool,
+    fov_kick: f32, // How much wider than normal the camera's field of view has been made for sprinting
 }
 
 impl Player {
-    pub fn new(hitbox: BBox) -> Self {
+    pub fn new(hitbox: BBox, eye_height: f32) -> Self {
         Self {
             hitbox: hitbox,
+            eye_height,
             vx: 0.0,
             vy: 0.0,
             vz: 0.0,
             facing_direction: cgmath::vec3(0.0, 0.0, 0.0),
-            speed: 0.5,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
-            is_up_pressed: false,
-            is_down_pressed: false,
-            is_gravity_pressed: false,
+            params: PlayerParams::default(),
+            input: PlayerInput::default(),
             do_gravity: true,
-            jump_pressed: false,
             can_jump: false,
-            x_pos_blocked: false,
-            x_neg_blocked: false,
-            y_pos_blocked: false,
-            y_neg_blocked: false,
-            z_pos_blocked: false,
-            z_neg_blocked: false,
+            crouching: false,
+            sprinting: false,
+            fov_kick: 0.0,
         }
     }
     pub fn get_pos(&self) -> Pos3 {
         return self.hitbox.center;
     }
-    pub fn change_pos(&mut self, x: f32, y: f32, z: f32) {
-        if (x > 0.0 && !self.x_pos_blocked) || (x < 0.0 && !self.x_neg_blocked) {
-            self.hitbox.center.x += x;
+    /// Where the camera goes, eye_height up from the player's feet (less crouch_drop while crouching)
+    pub fn eye(&self) -> Pos3 {
+        let mut eye_height = self.eye_height;
+        if self.crouching {
+            eye_height -= self.params.crouch_drop;
         }
-        if (y > 0.0 && !self.y_pos_blocked) || (y < 0.0 && !self.y_neg_blocked) {
-            self.hitbox.center.y += y;
+        self.hitbox.center + Vec3::new(0.0, eye_height - self.hitbox.half_extents.y, 0.0)
+    }
+    pub fn is_crouching(&self) -> bool {
+        self.crouching
+    }
+    pub fn is_sprinting(&self) -> bool {
+        self.sprinting
+    }
+    /// Snapshot of the player for saving. The hotbar slot lives in the game's rules, so it's passed in
+    pub fn state(&self, camera: &Camera, hotbar_slot: u8) -> PlayerState {
+        // Saved standing up, since crouching doesn't last through a reload
+        let mut position = self.hitbox.center;
+        if self.crouching {
+            position.y += self.params.crouch_drop / 2.0;
         }
-        if (z > 0.0 && !self.z_pos_blocked) || (z < 0.0 && !self.z_neg_blocked) {
-            self.hitbox.center.z += z;
+        PlayerState {
+            position: position.into(),
+            velocity: [self.vx, self.vy, self.vz],
+            facing_direction: self.facing_direction.into(),
+            do_gravity: self.do_gravity,
+            hotbar_slot,
+            look_direction: (camera.target - camera.eye).into(),
+            camera_up: camera.up.into(),
         }
     }
-    pub fn reset_blocked(&mut self) {
-        self.x_pos_blocked = false;
-        self.x_neg_blocked = false;
-        self.y_pos_blocked = false;
-        self.y_neg_blocked = false;
-        self.z_pos_blocked = false;
-        self.z_neg_blocked = false;
+    /// Puts the player and camera back where a saved state left them
+    pub fn restore(&mut self, state: &PlayerState, camera: &mut Camera) {
+        if self.crouching {
+            self.resize(self.params.crouch_drop);
+            self.crouching = false;
+        }
+        camera.fovy -= self.fov_kick;
+        self.fov_kick = 0.0;
+        self.sprinting = false;
+        self.hitbox.center = state.position.into();
+        self.vx = state.velocity[0];
+        self.vy = state.velocity[1];
+        self.vz = state.velocity[2];
+        self.facing_direction = state.facing_direction.into();
+        self.do_gravity = state.do_gravity;
+        self.can_jump = false;
+        camera.eye = self.eye();
+        camera.target = camera.eye + Vec3::from(state.look_direction);
+        camera.up = state.camera_up.into();
     }
-    pub fn update(&mut self, camera: &mut Camera, chunks: &Vec<Chunk>) {
-        //change position based on velocity
-        if !self.y_neg_blocked {
-            self.vy -= 0.005;
-            if self.vy <= -0.1 {
-                //terminal velocity
-                self.vy = -0.1;
+    /// Moves the player dt seconds forward, then puts the camera at their eyes
+    pub fn update(&mut self, camera: &mut Camera, world: &World, dt: f32) {
+        let input = self.input;
+        let params = &self.params;
+        if input.toggle_gravity {
+            self.do_gravity = !self.do_gravity;
+        }
+        if self.do_gravity {
+            self.vy = (self.vy - params.gravity * dt).max(-params.terminal_velocity);
+            if input.jump && self.can_jump {
+                // Leaving the ground straight away, so the jump gets air control too
+                self.vy = params.jump_speed;
+                self.can_jump = false;
             }
         } else {
             self.vy = 0.0;
         }
-        if self.jump_pressed && self.can_jump {
-            self.vy = 0.15;
-            self.can_jump = false;
-        }
 
-        if self.do_gravity {
-            if !collide_y(self.hitbox, &chunks, self.vy) {
-                self.change_pos(0.0, self.vy, 0.0);
+        // Crouching takes the top off of the hitbox, and standing back up needs room for it
+        if input.crouch && !self.crouching {
+            self.resize(-self.params.crouch_drop);
+            self.crouching = true;
+        } else if !input.crouch && self.crouching {
+            let crouched = self.hitbox;
+            let room = world.sweep(crouched, Vec3::new(0.0, self.params.crouch_drop, 0.0));
+            if room.normal[1] == 0 {
+                self.resize(self.params.crouch_drop);
+                self.crouching = false;
             }
         }
+        self.sprinting = input.sprint && input.forward && !self.crouching;
 
-        //change camera position to player position
-        let mut forward = camera.target - camera.eye;
-        forward.y = 0.0;
-        let forward_norm = forward.normalize();
-        let _forward_mag = forward.magnitude();
-        let right = forward_norm.cross(camera.up);
-        if self.is_forward_pressed {
-            let movement = forward_norm * self.speed;
-            if !collide_x(self.hitbox, &chunks, movement.x) {
-                self.change_pos(movement.x, 0.0, 0.0);
-            }
-            if !collide_z(self.hitbox, &chunks, movement.z) {
-                self.change_pos(0.0, 0.0, movement.z);
-            }
+        // Walk along the ground in whichever direction the camera is facing
+        let wish = input.wish_direction(camera.target - camera.eye);
+        let speed = if self.crouching {
+            self.params.crouch_speed
+        } else if self.sprinting {
+            self.params.sprint_speed
+        } else {
+            self.params.walk_speed
+        };
+        self.accelerate(wish, speed, dt);
+
+        let mut movement = Vec3::new(self.vx, self.vy, self.vz) * dt;
+        if input.up {
+            movement.y += self.params.walk_speed * dt;
         }
-        if self.is_backward_pressed {
-            let movement = -1.0 * forward_norm * self.speed;
-            if !collide_x(self.hitbox, &chunks, movement.x) {
-                self.change_pos(movement.x, 0.0, 0.0);
-            }
-            if !collide_z(self.hitbox, &chunks, movement.z) {
-                self.change_pos(0.0, 0.0, movement.z);
-            }
+        if input.down {
+            movement.y -= self.params.walk_speed * dt;
         }
-        if self.is_right_pressed {
-            let movement = right * self.speed;
-            if !collide_x(self.hitbox, &chunks, movement.x) {
-                self.change_pos(movement.x, 0.0, 0.0);
-            }
-            if !collide_z(self.hitbox, &chunks, movement.z) {
-                self.change_pos(0.0, 0.0, movement.z);
-            }
+        if self.crouching && self.do_gravity && self.can_jump {
+            movement = self.keep_off_edges(world, movement);
         }
-        if self.is_left_pressed {
-            let movement = -1.0 * right * self.speed;
-            if !collide_x(self.hitbox, &chunks, movement.x) {
-                self.change_pos(movement.x, 0.0, 0.0);
-            }
-            if !collide_z(self.hitbox, &chunks, movement.z) {
-                self.change_pos(0.0, 0.0, movement.z);
-            }
+
+        let sweep = world.sweep(self.hitbox, movement);
+        self.hitbox = sweep.hitbox;
+        // Running into something stops the player going that way
+        if sweep.normal[0] != 0 {
+            self.vx = 0.0;
         }
-        if self.is_up_pressed {
-            if !collide_y(self.hitbox, &chunks, self.speed) {
-                self.change_pos(0.0, self.speed, 0.0);
-            }
+        if sweep.normal[1] != 0 {
+            self.vy = 0.0;
+        }
+        if sweep.normal[2] != 0 {
+            self.vz = 0.0;
         }
-        if self.is_down_pressed {
-            if !collide_y(self.hitbox, &chunks, -1.0 * self.speed) {
-                self.change_pos(0.0, -1.0 * self.speed, 0.0);
+        self.can_jump = sweep.on_ground();
+
+        // set camera pos to the player's eyes
+        let player_diff = self.eye() - camera.eye;
+        camera.eye += player_diff;
+        camera.target += player_diff;
+
+        // Widen the view while sprinting
+        let kick = if self.sprinting {
+            self.params.sprint_fov
+        } else {
+            0.0
+        };
+        let most = self.params.sprint_fov * dt / SPRINT_FOV_TIME;
+        let change = (kick - self.fov_kick).max(-most).min(most);
+        self.fov_kick += change;
+        camera.fovy += change;
+    }
+    /// Makes the hitbox taller (or shorter) by grow, keeping the player's feet where they are
+    fn resize(&mut self, grow: f32) {
+        self.hitbox.half_extents.y += grow / 2.0;
+        self.hitbox.center.y += grow / 2.0;
+    }
+    /// Cuts down movement along the ground that would take the player off of a ledge
+    fn keep_off_edges(&mut self, world: &World, mut movement: Vec3) -> Vec3 {
+        let hitbox = self.hitbox;
+        let supported = |x: f32, z: f32| {
+            world.is_on_ground(BBox {
+                center: hitbox.center + Vec3::new(x, 0.0, z),
+                ..hitbox
+            })
+        };
+        let back_off = |distance: f32| {
+            if distance.abs() <= EDGE_STEP {
+                0.0
+            } else {
+                distance - EDGE_STEP * distance.signum()
             }
+        };
+        let (x, z) = (movement.x, movement.z);
+        while movement.x != 0.0 && !supported(movement.x, 0.0) {
+            movement.x = back_off(movement.x);
         }
-        if self.is_gravity_pressed {
-            self.do_gravity = !self.do_gravity;
+        while movement.z != 0.0 && !supported(0.0, movement.z) {
+            movement.z = back_off(movement.z);
         }
-
-        if !collide_y(self.hitbox, &chunks, self.vy) {
-            self.hitbox.center.y += self.vy;
+        // Each way might be fine alone but not together, like when heading straight off of a corner
+        while movement.x != 0.0 && movement.z != 0.0 && !supported(movement.x, movement.z) {
+            movement.x = back_off(movement.x);
+            movement.z = back_off(movement.z);
         }
-        if collide_y(self.hitbox, &chunks, -(self.hitbox.halfwidth - 0.01)) {
-            self.can_jump = true;
+        if movement.x != x {
+            self.vx = 0.0;
         }
-        let player_pos = self.get_pos();
-        let player_p3 = cgmath::point3(player_pos.x, player_pos.y, player_pos.z);
-
-        // set camera pos to player pos
-        let player_diff = player_p3 - camera.eye;
-        camera.eye += player_diff;
-        camera.target += player_diff;
+        if movement.z != z {
+            self.vz = 0.0;
+        }
+        movement
+    }
+    /// Speeds the player up towards speed in the wish direction, or slows them down to a stop
+    /// if there isn't one. Only air_control of that applies while they're off the ground (unless
+    /// they're flying)
+    fn accelerate(&mut self, wish: Vec3, speed: f32, dt: f32) {
+        let params = &self.params;
+        let mut rate = if wish == Vec3::zero() {
+            params.friction
+        } else {
+            params.acceleration
+        };
+        if self.do_gravity && !self.can_jump {
+            rate *= params.air_control;
+        }
+        let velocity = Vec3::new(self.vx, 0.0, self.vz);
+        let change = wish * speed - velocity;
+        let velocity = if change.magnitude() > rate * dt {
+            velocity + change.normalize_to(rate * dt)
+        } else {
+            velocity + change
+        };
+        self.vx = velocity.x;
+        self.vz = velocity.z;
     }
     pub fn process_events(&mut self, events: &Events) -> bool {
-        self.is_forward_pressed = events.key_held(VirtualKeyCode::W);
-        self.is_left_pressed = events.key_held(VirtualKeyCode::A);
-        self.is_backward_pressed = events.key_held(VirtualKeyCode::S);
-        self.is_right_pressed = events.key_held(VirtualKeyCode::D);
-        self.is_up_pressed = events.key_held(VirtualKeyCode::R);
-        self.is_down_pressed = events.key_held(VirtualKeyCode::F);
-        self.is_gravity_pressed = events.key_pressed(VirtualKeyCode::G);
-        self.jump_pressed = events.key_held(VirtualKeyCode::Space);
+        self.input = PlayerInput {
+            forward: events.key_held(VirtualKeyCode::W),
+            backward: events.key_held(VirtualKeyCode::S),
+            left: events.key_held(VirtualKeyCode::A),
+            right: events.key_held(VirtualKeyCode::D),
+            up: events.key_held(VirtualKeyCode::R),
+            down: events.key_held(VirtualKeyCode::F),
+            jump: events.key_held(VirtualKeyCode::Space),
+            sprint: events.key_held(VirtualKeyCode::LControl),
+            crouch: events.key_held(VirtualKeyCode::LShift),
+            toggle_gravity: events.key_pressed(VirtualKeyCode::G),
+        };
         true
     }
 }
@@ -1,24 +1,34 @@
 use crate::assets::{Asset2d, Assets, Object2d};
+use crate::blocks::{BlockRegistry, RenderMode, AIR, BLOCKS_FILE};
+use crate::breaking::{Breaking, Tool, CRACK_STAGES};
 use crate::camera::Camera;
 use crate::camera_control::CameraController;
-use crate::coordinates::*;
-use crate::model::Model;
+use crate::collision::RayHit;
+use crate::geom::BBox;
+use crate::jobs::ChunkState;
+use crate::light::VoxelPos;
+use crate::mesher::{chunk_mesh, ChunkMesh, Face, MeshData};
 use crate::model::*;
+use crate::streaming::ChunkStreamer;
 use crate::texture::Texture;
 use crate::voxel::*;
+use crate::world::{ChunkCoord, VoxelCoord, World};
 use crate::world_gen::*;
 use crate::Events;
 use crate::Game;
 use crate::Sound;
 use cgmath::prelude::*;
+use std::collections::HashMap;
 use std::iter;
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 pub type Pos3 = cgmath::Point3<f32>;
 pub type Pos2 = cgmath::Point2<f32>;
 pub type Mat4 = cgmath::Matrix4<f32>;
 
-pub const DT: f32 = 1.0 / 30.0;
+/// How far away blocks can be broken and placed, in world units
+pub const REACH: f32 = 5.0 * VOXEL_HALFWIDTH * 2.0;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -31,9 +41,25 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 #[derive(Copy, Clone)]
 pub struct TwoDID(usize, usize, pub bool);
 
+/// A chunk's meshes on the GPU, one for each RenderMode. They're None when the chunk has no faces drawn that way
 pub struct ChunkRender {
-    instance_data: Vec<Vec<InstanceRaw>>,
-    buffers: Vec<wgpu::Buffer>,
+    opaque: Option<Mesh>,
+    cutout: Option<Mesh>,
+    translucent: Option<TranslucentMesh>,
+}
+
+/// Translucent faces have to be drawn back to front, so their indices are sorted again whenever the camera moves
+struct TranslucentMesh {
+    mesh: Mesh,
+    data: MeshData,
+    sorted_from: (ChunkCoord, VoxelCoord), // Voxel the camera was in the last time the indices were sorted
+}
+
+/// How many chunks were drawn in the last frame, and how many were skipped for being off screen
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
 }
 
 #[repr(C)]
@@ -53,51 +79,6 @@ impl Uniforms {
         self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
     }
 }
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct InstanceRaw {
-    #[allow(dead_code)]
-    pub(crate) model: [[f32; 4]; 4],
-}
-
-impl InstanceRaw {
-    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            // We need to switch from using a step mode of Vertex to Instance
-            // This means that our shaders will only change to use the next
-            // instance when the shader starts processing a new instance
-            step_mode: wgpu::InputStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We don't have to do this in code though.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float4,
-                },
-            ],
-        }
-    }
-}
 pub struct Render {
     surface: wgpu::Surface,
     pub(crate) device: wgpu::Device,
@@ -106,7 +87,17 @@ pub struct Render {
     swap_chain: wgpu::SwapChain,
     pub(crate) size: winit::dpi::PhysicalSize<u32>,
     render_pipeline: wgpu::RenderPipeline,
-    voxel_model: Model,
+    cutout_pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    outline_buffer: wgpu::Buffer, // Lines around the edges of the target block
+    pub target: Option<RayHit>,   // The block the camera was looking at last frame, within REACH
+    crack_pipeline: wgpu::RenderPipeline,
+    crack_buffer: wgpu::Buffer, // Cracks over the face of the target block while it's being broken
+    pub breaking: Breaking,
+    pub tool: Tool, // What the player is breaking blocks with
+    pub blocks: Arc<BlockRegistry>,
+    block_material: Material, // Every block texture, as the layers of a texture array
     pub(crate) texture_layout: wgpu::BindGroupLayout,
     pub camera: Camera,
     pub camera_controller: CameraController,
@@ -114,10 +105,10 @@ pub struct Render {
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     depth_texture: Texture,
-    pub chunks: Vec<Chunk>, // chunks in the world (or to be rendered. TBD)
-    dynamic_chunks: Vec<ChunkRender>,
-    current_chunk: isize,
-    dynamic_center: usize,
+    pub world: World, // chunks in the world (or to be rendered. TBD)
+    dynamic_chunks: HashMap<ChunkCoord, ChunkRender>,
+    pub cull_stats: CullStats,   // For profiling
+    pub streamer: ChunkStreamer, // Which chunks around the player are loaded, and how far along the rest are
     buffers_2d: Vec<wgpu::Buffer>,
     bind_groups_2d: Vec<wgpu::BindGroup>,
     pub objects_2d: Vec<TwoDID>,
@@ -188,6 +179,32 @@ impl Render {
                 label: Some("texture_bind_group_layout"),
             });
 
+        let block_texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: false,
+                            filtering: true,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("block_texture_bind_group_layout"),
+            });
+
         let camera = Camera {
             eye: (10.0, 330.0, 10.0).into(),
             target: (1.0, 1.0, 1.0).into(),
@@ -210,27 +227,21 @@ impl Render {
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
-        let chunks = make_world();
         let res_dir = std::path::Path::new(env!("OUT_DIR")).join("content");
-        // Create voxel model struct. This is a simple cube that's used as base for every voxel
-        let voxel_model = Model::load(
+        let blocks = Arc::new(BlockRegistry::load(res_dir.join(BLOCKS_FILE)).unwrap());
+        let mut world = make_world(random_seed(), WorldGenParams::default(), blocks.clone());
+        // Each block face picks its layer of the array, so a whole chunk draws with one material
+        let block_textures: Vec<_> = blocks
+            .textures()
+            .iter()
+            .map(|texture| res_dir.join(texture))
+            .collect();
+        let block_material = Material::new(
             &device,
-            &queue,
-            &texture_bind_group_layout,
-            res_dir.join("cube.obj"),
-        )
-        .unwrap();
-
-        // Create Dynamic Chunks
-        let mut dynamic_chunks: Vec<ChunkRender> = Vec::with_capacity(chunks.len() - 1);
-        for i in 0..(4 * RENDER_RADIUS.0.pow(2) * RENDER_RADIUS.1) {
-            dynamic_chunks.push(chunk_to_raw(
-                voxel_model.materials.len(),
-                &device,
-                &chunks[i],
-                i,
-            ))
-        }
+            &block_texture_bind_group_layout,
+            "blocks".to_string(),
+            Texture::load_array(&device, &queue, &block_textures, "block textures").unwrap(),
+        );
 
         let buffers_2d = vec![];
         let bind_groups_2d = vec![];
@@ -321,58 +332,81 @@ impl Render {
             label: Some("uniform_bind_group"),
         });
 
-        let vs_module = device.create_shader_module(&wgpu::include_spirv!("shader.vert.spv"));
+        let vs_module = device.create_shader_module(&wgpu::include_spirv!("voxel.vert.spv"));
+        let voxel_fs_module = device.create_shader_module(&wgpu::include_spirv!("voxel.frag.spv"));
+        let cutout_fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("voxel_cutout.frag.spv"));
 
         let depth_texture = Texture::create_depth_texture(&device, &sc_desc, "depth_texture");
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                bind_group_layouts: &[&block_texture_bind_group_layout, &uniform_bind_group_layout],
                 push_constant_ranges: &[],
             });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render 3d Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vs_module,
-                entry_point: "main",
-                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    alpha_blend: wgpu::BlendState::REPLACE,
-                    color_blend: wgpu::BlendState::REPLACE,
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-                // Setting this to true requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+        let voxel_pipeline = |fs_module, render| {
+            voxel_pipeline(
+                &device,
+                &render_pipeline_layout,
+                &vs_module,
+                fs_module,
+                sc_desc.format,
+                render,
+            )
+        };
+        let render_pipeline = voxel_pipeline(&voxel_fs_module, RenderMode::Opaque);
+        let cutout_pipeline = voxel_pipeline(&cutout_fs_module, RenderMode::Cutout);
+        let translucent_pipeline = voxel_pipeline(&voxel_fs_module, RenderMode::Translucent);
+
+        let outline_vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("outline.vert.spv"));
+        let outline_fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("outline.frag.spv"));
+        let crack_vs_module = device.create_shader_module(&wgpu::include_spirv!("crack.vert.spv"));
+        let crack_fs_module = device.create_shader_module(&wgpu::include_spirv!("crack.frag.spv"));
+        let overlay_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Overlay Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let outline_pipeline = overlay_pipeline(
+            &device,
+            &overlay_pipeline_layout,
+            (&outline_vs_module, &outline_fs_module),
+            LineVertex::desc(),
+            wgpu::PrimitiveTopology::LineList,
+            sc_desc.format,
+        );
+        let crack_pipeline = overlay_pipeline(
+            &device,
+            &overlay_pipeline_layout,
+            (&crack_vs_module, &crack_fs_module),
+            CrackVertex::desc(),
+            wgpu::PrimitiveTopology::TriangleList,
+            sc_desc.format,
+        );
+        let outline_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Vertex Buffer"),
+            contents: bytemuck::cast_slice(&outline_vertices([0, 0, 0])),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        });
+        let crack_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Crack Vertex Buffer"),
+            contents: bytemuck::cast_slice(
+                &[CrackVertex {
+                    position: [0.0; 3],
+                    tex_coords: [0.0; 2],
+                    stage: 0.0,
+                }; CRACK_VERTICES],
+            ),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
         });
 
+        let current_chunk = World::world_to_chunk(camera.eye).0;
+        let mut streamer = ChunkStreamer::new(worker_count());
+        streamer.stream(&mut world, current_chunk);
         Self {
             surface,
             device,
@@ -381,18 +415,28 @@ impl Render {
             swap_chain,
             size,
             render_pipeline,
+            cutout_pipeline,
+            translucent_pipeline,
+            outline_pipeline,
+            outline_buffer,
+            target: None,
+            crack_pipeline,
+            crack_buffer,
+            breaking: Breaking::default(),
+            tool: Tool::hand(),
             texture_layout: texture_bind_group_layout,
-            voxel_model,
+            blocks,
+            block_material,
             camera,
             camera_controller,
             uniforms,
             uniform_buffer,
             uniform_bind_group,
             depth_texture,
-            chunks,
-            dynamic_chunks,
-            current_chunk: 0,
-            dynamic_center: 0,
+            world,
+            dynamic_chunks: HashMap::new(),
+            cull_stats: CullStats::default(),
+            streamer,
             buffers_2d,
             bind_groups_2d,
             objects_2d,
@@ -444,42 +488,66 @@ impl Render {
     pub fn set_2d_bind_groups(&mut self, assets_2d: &Vec<Asset2d>) {
         for asset in assets_2d {
             let diffuse_texture = Texture::load(&self.device, &self.queue, &asset.0).unwrap();
-
-            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &self.texture_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: Some("bind_group"), // change to be dependent
-            });
+            let bind_group = self.bind_group_2d(&diffuse_texture);
             self.bind_groups_2d.push(bind_group);
         }
     }
 
-    pub fn input(&mut self, events: &Events, selected_block: u8) -> bool {
+    /// Use to set up (or replace) the texture at index bg with an image made at runtime, like text.
+    /// bg can be one past the last texture to add a new one
+    pub fn set_2d_image(&mut self, bg: usize, image: &image::RgbaImage) {
+        let image = image::DynamicImage::ImageRgba8(image.clone());
+        let texture =
+            Texture::from_image(&self.device, &self.queue, &image, Some("2d image")).unwrap();
+        let bind_group = self.bind_group_2d(&texture);
+        match self.bind_groups_2d.get_mut(bg) {
+            Some(old) => *old = bind_group,
+            None => {
+                assert_eq!(bg, self.bind_groups_2d.len(), "2d textures can't have gaps");
+                self.bind_groups_2d.push(bind_group);
+            }
+        }
+    }
+
+    fn bind_group_2d(&self, texture: &Texture) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("bind_group"), // change to be dependent
+        })
+    }
+
+    pub fn input(&mut self, events: &Events, selected_block: u8, player: &BBox) -> bool {
         self.camera_controller.process_events(events);
-        let new_index = world_to_chunk(self.camera.eye).0 as isize;
-        if self.current_chunk != new_index {
-            self.current_chunk = new_index;
-            self.change_render();
+        let new_chunk = World::world_to_chunk(self.camera.eye).0;
+        if self.streamer.center() != new_chunk {
+            self.streamer.stream(&mut self.world, new_chunk);
+            self.drop_hidden_chunks();
         }
-        if events.mouse_pressed(0) {
-            self.left_click();
-        } else if events.mouse_pressed(1) {
-            self.right_click(selected_block);
+        self.update_breaking(events.mouse_held(0));
+        if events.mouse_pressed(1) {
+            self.right_click(selected_block, player);
         }
         true
     }
 
     pub fn update(&mut self) {
         self.camera_controller.update_camera(&mut self.camera);
+        self.receive_chunks();
+    }
+
+    /// How far along the chunk at coord is in being loaded
+    pub fn chunk_state(&self, coord: ChunkCoord) -> ChunkState {
+        self.streamer.state(coord)
     }
 
     pub(crate) fn render<R, G: Game<StaticData = R>>(
@@ -488,19 +556,6 @@ impl Render {
         _rules: &R,
         _assets: &mut Assets,
     ) -> Result<(), wgpu::SwapChainError> {
-        // Update buffers based on dynamics
-
-        // Add buffers to the queue
-        for i in 0..self.dynamic_chunks.len() {
-            for j in 0..self.dynamic_chunks[i].instance_data.len() {
-                self.queue.write_buffer(
-                    &self.dynamic_chunks[i].buffers[j],
-                    0,
-                    bytemuck::cast_slice(&self.dynamic_chunks[i].instance_data[j]),
-                );
-            }
-        }
-
         self.uniforms.update_view_proj(&self.camera);
         self.queue.write_buffer(
             &self.uniform_buffer,
@@ -508,6 +563,39 @@ impl Render {
             bytemuck::cast_slice(&[self.uniforms]),
         );
 
+        self.sort_translucent();
+
+        self.target = self.pick();
+        if let Some(target) = self.target {
+            self.queue.write_buffer(
+                &self.outline_buffer,
+                0,
+                bytemuck::cast_slice(&outline_vertices(target.voxel)),
+            );
+        }
+        let crack = self.crack_vertices();
+        if let Some(vertices) = &crack {
+            self.queue
+                .write_buffer(&self.crack_buffer, 0, bytemuck::cast_slice(vertices));
+        }
+
+        // Skip chunks that are entirely off screen
+        let frustum = self.camera.frustum();
+        let chunk_size = cgmath::Vector3::from_value(CHUNK_SIZE as f32 * VOXEL_HALFWIDTH * 2.0);
+        let visible: Vec<(ChunkCoord, &ChunkRender)> = self
+            .dynamic_chunks
+            .iter()
+            .filter(|(coord, _)| {
+                let min = World::chunk_to_world(**coord);
+                frustum.intersects_aabb(min, min + chunk_size)
+            })
+            .map(|(coord, chunk_render)| (*coord, chunk_render))
+            .collect();
+        self.cull_stats = CullStats {
+            drawn: visible.len(),
+            culled: self.dynamic_chunks.len() - visible.len(),
+        };
+
         let frame = self.swap_chain.get_current_frame()?.output;
 
         let mut encoder = self
@@ -541,20 +629,48 @@ impl Render {
                     stencil_ops: None,
                 }),
             });
+            // One draw per chunk for each pass, with every block texture in the same material.
+            // Opaque faces go first so that cutout and translucent faces can be depth tested against them
             render_pass.set_pipeline(&self.render_pipeline);
-
-            // Render each voxel buffer, passing information about the material that we're using (the last argument in the function call)
-            // Materials info is stored in  "cube.mtl"
-            for i in 0..self.dynamic_chunks.len() {
-                for j in 0..self.dynamic_chunks[i].instance_data.len() {
-                    render_pass.set_vertex_buffer(1, self.dynamic_chunks[i].buffers[j].slice(..));
-                    render_pass.draw_chunk(
-                        &self.voxel_model,
-                        &self.uniform_bind_group,
-                        0..self.dynamic_chunks[i].instance_data[j].len() as u32,
-                        j,
-                    );
-                }
+            for mesh in visible
+                .iter()
+                .filter_map(|(_, chunk_render)| chunk_render.opaque.as_ref())
+            {
+                render_pass.draw_mesh(mesh, &self.block_material, &self.uniform_bind_group);
+            }
+            render_pass.set_pipeline(&self.cutout_pipeline);
+            for mesh in visible
+                .iter()
+                .filter_map(|(_, chunk_render)| chunk_render.cutout.as_ref())
+            {
+                render_pass.draw_mesh(mesh, &self.block_material, &self.uniform_bind_group);
+            }
+            // Translucent chunks are drawn furthest first, like the faces inside of them
+            let eye = self.camera.eye;
+            let mut translucent: Vec<(f32, &Mesh)> = visible
+                .iter()
+                .filter_map(|(coord, chunk_render)| {
+                    let center = World::chunk_to_world(*coord) + chunk_size / 2.0;
+                    let translucent = chunk_render.translucent.as_ref()?;
+                    Some((center.distance2(eye), &translucent.mesh))
+                })
+                .collect();
+            translucent.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+            render_pass.set_pipeline(&self.translucent_pipeline);
+            for (_, mesh) in translucent {
+                render_pass.draw_mesh(mesh, &self.block_material, &self.uniform_bind_group);
+            }
+            if self.target.is_some() {
+                render_pass.set_pipeline(&self.outline_pipeline);
+                render_pass.set_vertex_buffer(0, self.outline_buffer.slice(..));
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.draw(0..OUTLINE_VERTICES as u32, 0..1);
+            }
+            if crack.is_some() {
+                render_pass.set_pipeline(&self.crack_pipeline);
+                render_pass.set_vertex_buffer(0, self.crack_buffer.slice(..));
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.draw(0..CRACK_VERTICES as u32, 0..1);
             }
             // set 2d pipeline, make sure texture is updated, provide a texture bindgroup
             // call draw on what vertices to draw
@@ -575,282 +691,426 @@ impl Render {
         Ok(())
     }
 
-    fn left_click(&mut self) {
-        let forward = self.camera.target - self.camera.eye;
-        for length in 0..6 {
-            let (i, (x, y, z)) = world_to_chunk(
-                self.camera.eye
-                    + (forward.normalize() * VOXEL_HALFWIDTH * (2.0 * length as f32 + 0.1)),
-            );
-            if self.chunks[i].data[x][y][z] == 7 {
-                break;
-            }
-            if self.chunks[i].data[x][y][z] != 0 {
-                let difference = i as isize - self.current_chunk;
-                let selected_block = self.chunks[i].data[x][y][z];
-                if selected_block == 1 || selected_block == 2 {
-                    self.sound.play_sound("dirt".to_string());
-                } else {
-                    self.sound.play_sound("stone".to_string());
-                }
-                if difference == 0 {
-                    self.chunks[i].data[x][y][z] = 0;
-                    self.dynamic_chunks[self.dynamic_center] = chunk_to_raw(
-                        self.voxel_model.materials.len(),
-                        &self.device,
-                        &self.chunks[i],
-                        i,
-                    );
-                } else if difference % (WORLD_DIMS.1 * WORLD_DIMS.2) as isize == 0 {
-                    // Difference is in X
-                    self.chunks[i].data[x][y][z] = 0;
-                    let offset =
-                        (4 * RENDER_RADIUS.1 * RENDER_RADIUS.0) as isize * difference.signum();
-                    self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                        chunk_to_raw(
-                            self.voxel_model.materials.len(),
-                            &self.device,
-                            &self.chunks[i],
-                            i,
-                        );
-                } else if difference % WORLD_DIMS.2 as isize == 0 {
-                    // DIfference is in Y
-                    self.chunks[i].data[x][y][z] = 0;
-                    let offset = (2 * RENDER_RADIUS.0) as isize * difference.signum();
-                    self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                        chunk_to_raw(
-                            self.voxel_model.materials.len(),
-                            &self.device,
-                            &self.chunks[i],
-                            i,
-                        );
-                } else {
-                    self.chunks[i].data[x][y][z] = 0;
-                    let offset = difference.signum();
-                    self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                        chunk_to_raw(
-                            self.voxel_model.materials.len(),
-                            &self.device,
-                            &self.chunks[i],
-                            i,
-                        );
+    /// The block the camera is looking at right now, if it's close enough to reach
+    pub fn pick(&self) -> Option<RayHit> {
+        self.world.looked_at(&self.camera, REACH)
+    }
+
+    /// Keeps breaking the target block while the mouse is held down on it, and breaks it once it's been
+    /// held long enough for its hardness
+    fn update_breaking(&mut self, held: bool) {
+        let blocks = self.blocks.clone();
+        let hit = if held { self.pick() } else { None };
+        let target = hit.and_then(|hit| {
+            self.world
+                .voxel_at(hit.voxel)
+                .map(|voxel| (hit.voxel, blocks.get(voxel)))
+        });
+        if self.breaking.update(target, &self.tool, crate::DT) {
+            if let Some((voxel, block)) = target {
+                if let Some(sound) = &block.break_sound {
+                    self.sound.play_sound(sound.clone());
                 }
-                break;
+                let point = World::voxel_center(voxel);
+                self.world.set_voxel(point, AIR);
+                self.remesh_around(point);
             }
         }
     }
 
-    fn right_click(&mut self, selected_block: u8) {
-        let forward = self.camera.target - self.camera.eye;
-        for length in 2..7 {
-            let (i, (x, y, z)) = world_to_chunk(
-                self.camera.eye
-                    + (forward.normalize() * VOXEL_HALFWIDTH * (2.0 * length as f32 + 0.1)),
-            );
-            let (place_i, (px, py, pz)) = world_to_chunk(
-                self.camera.eye
-                    + (forward.normalize() * VOXEL_HALFWIDTH * (2.0 * (length - 1) as f32 + 0.1)),
-            );
+    fn right_click(&mut self, selected_block: u8, player: &BBox) {
+        let hit = match self.pick() {
+            Some(hit) => hit,
+            None => return,
+        };
+        let place = hit.adjacent();
+        // Don't place a block anywhere the player's body is
+        if place == hit.voxel || World::overlaps_voxel(player, place) {
+            return;
+        }
+        if self.world.voxel_at(place) == Some(AIR) {
+            let point = World::voxel_center(place);
+            self.world.set_voxel(point, selected_block);
+            self.remesh_around(point);
+            if let Some(sound) = &self.blocks.get(selected_block).place_sound {
+                self.sound.play_sound(sound.clone());
+            }
+        }
+    }
 
-            // If we have an empty voxel, then place stuff
-            if self.chunks[i].data[x][y][z] != 0 {
-                if self.chunks[place_i].data[px][py][pz] == 0 {
-                    let difference = place_i as isize - self.current_chunk;
-                    if difference == 0 {
-                        self.chunks[place_i].data[px][py][pz] = selected_block;
-                        self.dynamic_chunks[self.dynamic_center] = chunk_to_raw(
-                            self.voxel_model.materials.len(),
-                            &self.device,
-                            &self.chunks[i],
-                            i,
-                        );
-                    } else if difference % (WORLD_DIMS.1 * WORLD_DIMS.2) as isize == 0 {
-                        // Difference is in X
-                        self.chunks[place_i].data[px][py][pz] = selected_block; // TODO: SELECTED BLOCK
-                        let offset =
-                            (4 * RENDER_RADIUS.1 * RENDER_RADIUS.0) as isize * difference.signum();
-                        self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                            chunk_to_raw(
-                                self.voxel_model.materials.len(),
-                                &self.device,
-                                &self.chunks[i],
-                                i,
-                            );
-                    } else if difference % WORLD_DIMS.2 as isize == 0 {
-                        // DIfference is in Y
-                        self.chunks[place_i].data[px][py][pz] = selected_block; // TODO: SELECTED BLOCK
-                        let offset = (2 * RENDER_RADIUS.0) as isize * difference.signum();
-                        self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                            chunk_to_raw(
-                                self.voxel_model.materials.len(),
-                                &self.device,
-                                &self.chunks[i],
-                                i,
-                            );
-                    } else {
-                        self.chunks[place_i].data[px][py][pz] = selected_block; // TODO: SELECTED BLOCK
-                        let offset = difference.signum();
-                        self.dynamic_chunks[(self.dynamic_center as isize + offset) as usize] =
-                            chunk_to_raw(
-                                self.voxel_model.materials.len(),
-                                &self.device,
-                                &self.chunks[i],
-                                i,
-                            );
-                    }
-                }
-                if selected_block == 1 || selected_block == 2 {
-                    self.sound.play_sound("dirt".to_string());
-                } else {
-                    self.sound.play_sound("stone".to_string());
-                }
-                break;
+    /// Cracks over the face of the target block that's being broken, if there is one
+    fn crack_vertices(&self) -> Option<[CrackVertex; CRACK_VERTICES]> {
+        let target = self.target?;
+        let stage = self.breaking.stage()?;
+        let (voxel, _) = self.breaking.target?;
+        let axis = target.face_normal.iter().position(|n| *n != 0)?;
+        if voxel != target.voxel {
+            return None;
+        }
+        let size = VOXEL_HALFWIDTH * 2.0;
+        let margin = 0.02;
+        let normal = target.face_normal[axis];
+        // The face is on the far side of the voxel if it points the positive way
+        let plane =
+            (target.voxel[axis] + (normal > 0) as i32) as f32 * size + normal as f32 * margin;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let stage = (stage + 1) as f32 / CRACK_STAGES as f32;
+        let corner = |cu: usize, cv: usize| {
+            let mut position = [0.0; 3];
+            position[axis] = plane;
+            position[u] = (target.voxel[u] + cu as i32) as f32 * size;
+            position[v] = (target.voxel[v] + cv as i32) as f32 * size;
+            CrackVertex {
+                position,
+                tex_coords: [cu as f32, cv as f32],
+                stage,
+            }
+        };
+        Some([
+            corner(0, 0),
+            corner(1, 0),
+            corner(1, 1),
+            corner(0, 0),
+            corner(1, 1),
+            corner(0, 1),
+        ])
+    }
+
+    /// Sorts the translucent faces of every chunk back to front again if the camera moved into another voxel
+    fn sort_translucent(&mut self) {
+        let eye = self.camera.eye;
+        let eye_voxel = World::world_to_chunk(eye);
+        for translucent in self
+            .dynamic_chunks
+            .values_mut()
+            .filter_map(|chunk_render| chunk_render.translucent.as_mut())
+        {
+            if translucent.sorted_from != eye_voxel {
+                let indices = translucent.data.sorted_back_to_front(eye.into());
+                self.queue.write_buffer(
+                    &translucent.mesh.index_buffer,
+                    0,
+                    bytemuck::cast_slice(&indices),
+                );
+                translucent.sorted_from = eye_voxel;
             }
         }
     }
 
-    fn change_render(&mut self) {
-        // Determine the startspot for our chunk
-        let (x, y, z) = index_to_world(self.current_chunk as usize);
-        // Set the start and end variables for our iteration
-        // X
-        let x_start = x as isize - RENDER_RADIUS.0 as isize;
-        let x_end = (x_start + (RENDER_RADIUS.0 as isize) * 2) as usize;
-        let x_start = (x_start).clamp(
-            0,
-            WORLD_DIMS.0 as isize - (RENDER_RADIUS.0 * 2 + 1) as isize,
-        ) as usize;
-        // Y
-        let y_start = y as isize - RENDER_RADIUS.1 as isize;
-        let y_end = (y_start + RENDER_RADIUS.1 as isize * 2) as usize;
-        let y_start = (y_start).clamp(
-            0,
-            WORLD_DIMS.1 as isize - (RENDER_RADIUS.1 * 2 + 1) as isize,
-        ) as usize;
-        // // Z
-        let z_start = z as isize - RENDER_RADIUS.0 as isize;
-        let z_end = (z_start + RENDER_RADIUS.0 as isize * 2) as usize;
-        let z_start = (z_start as isize).clamp(
-            0,
-            WORLD_DIMS.2 as isize - (RENDER_RADIUS.0 * 2 + 1) as isize,
-        ) as usize;
-
-        let mut dynamic_chunks: Vec<ChunkRender> =
-            Vec::with_capacity((2 * RENDER_RADIUS.0 + 1).pow(2) * (2 * RENDER_RADIUS.1 + 1));
-        let mut center: usize = 0;
-        for world_x in x_start..(x_end + 1) {
-            let x = world_x * WORLD_DIMS.1 * WORLD_DIMS.2;
-            for world_y in y_start..(y_end + 1) {
-                let y = world_y * WORLD_DIMS.2;
-                for world_z in z_start..(z_end + 1) {
-                    let index =
-                        (x + y + world_z).clamp(0, WORLD_DIMS.0 * WORLD_DIMS.1 * WORLD_DIMS.2 - 1);
-                    if index == self.current_chunk as usize {
-                        self.dynamic_center = center;
-                    } else {
-                        center += 1;
-                    }
-                    dynamic_chunks.push(chunk_to_raw(
-                        self.voxel_model.materials.len(),
-                        &self.device,
-                        &self.chunks[index],
-                        index,
-                    ))
-                }
+    /// Rebuilds the render data of a chunk after one of its voxels changed
+    fn remesh(&mut self, coord: ChunkCoord) {
+        if !self.dynamic_chunks.contains_key(&coord) {
+            return;
+        }
+        // Edits are meshed right away so the player sees them on the next frame.
+        // Marking the chunk ready also makes any mesh job still in flight get ignored
+        if let Some(neighborhood) = self.world.neighborhood(coord) {
+            let mesh_data = chunk_mesh(&self.blocks, &neighborhood, coord);
+            self.dynamic_chunks.insert(
+                coord,
+                chunk_to_raw(&self.device, coord, mesh_data, self.camera.eye),
+            );
+            self.streamer.meshed(coord);
+        }
+    }
+
+    /// Rebuilds the chunk holding a voxel that changed, along with any chunks it borders.
+    /// Chunks further away that the change lit up or darkened get meshed on the workers
+    fn remesh_around(&mut self, point: Pos3) {
+        let (coord, voxel) = World::world_to_chunk(point);
+        let mut remeshed = vec![coord];
+        let voxel = [voxel.0, voxel.1, voxel.2];
+        for face in Face::ALL.iter() {
+            let border = if face.is_positive() {
+                CHUNK_SIZE - 1
+            } else {
+                0
+            };
+            if voxel[face.axis()] == border {
+                remeshed.push(World::neighbor_coord(coord, *face));
             }
         }
+        for coord in remeshed.iter() {
+            self.remesh(*coord);
+        }
+        let mut relit = self.world.take_relit();
+        relit.retain(|coord| !remeshed.contains(coord));
+        self.streamer.request_meshes(&self.world, relit);
+    }
 
-        self.dynamic_chunks = dynamic_chunks;
+    /// Swaps in a different world (e.g. one loaded from a save) and streams in the chunks around the camera.
+    /// Chunks the world already has are drawn as they are, not generated again
+    pub fn set_world(&mut self, world: World) {
+        self.world = world;
+        self.dynamic_chunks.clear();
+        let center = World::world_to_chunk(self.camera.eye).0;
+        self.streamer.reset(&mut self.world, center);
     }
-}
 
-fn chunk_to_raw(mat_count: usize, device: &wgpu::Device, chunk: &Chunk, i: usize) -> ChunkRender {
-    let mut instance_data: Vec<Vec<InstanceRaw>> = Vec::new();
-    for _ in 0..mat_count {
-        instance_data.push(Vec::new());
+    /// Stops drawing chunks that have been unloaded, or that are only kept around because they were edited
+    fn drop_hidden_chunks(&mut self) {
+        let streamer = &self.streamer;
+        self.dynamic_chunks
+            .retain(|coord, _| streamer.state(*coord) == ChunkState::Ready);
     }
 
-    let mut buffers: Vec<wgpu::Buffer> = Vec::new();
-
-    let mut mesh: [[[bool; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE] =
-        [[[false; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
-
-    let chunk_scale = (VOXEL_HALFWIDTH * 2.0) as usize * CHUNK_SIZE;
-    let origin_x = (i / (WORLD_DIMS.1 * WORLD_DIMS.2) * chunk_scale) as f32;
-    let origin_y = ((i % (WORLD_DIMS.1 * WORLD_DIMS.2)) / WORLD_DIMS.2 * chunk_scale) as f32;
-    let origin_z = ((i % (WORLD_DIMS.1 * WORLD_DIMS.2)) % WORLD_DIMS.2 * chunk_scale) as f32;
-
-    for x in 0..CHUNK_SIZE as usize {
-        for y in 0..CHUNK_SIZE as usize {
-            for z in 0..CHUNK_SIZE as usize {
-                let voxel = chunk.data[x][y][z];
-                // ADD MESHING HERE
-                if x != 0
-                    && (x + 1) != CHUNK_SIZE
-                    && y != 0
-                    && (y + 1) != CHUNK_SIZE
-                    && z != 0
-                    && (z + 1) != CHUNK_SIZE
-                {
-                    if voxel == 0 {
-                        mesh[x + 1][y][z] = true;
-                        mesh[x - 1][y][z] = true;
-                        mesh[x][y + 1][z] = true;
-                        mesh[x][y - 1][z] = true;
-                        mesh[x][y][z + 1] = true;
-                        mesh[x][y][z - 1] = true;
-                    }
-                } else {
-                    mesh[x][y][z] = true;
-                    if voxel == 0 {
-                        mesh[(x + 1).clamp(0, CHUNK_SIZE - 1) as usize][y][z] = true;
-                        mesh[(x as i32 - 1).clamp(0, CHUNK_SIZE as i32 - 1) as usize][y][z] = true;
-                        mesh[x][(y + 1).clamp(0, CHUNK_SIZE - 1) as usize][z] = true;
-                        mesh[x][(y as i32 - 1).clamp(0, CHUNK_SIZE as i32 - 1) as usize][z] = true;
-                        mesh[x][y][(z + 1).clamp(0, CHUNK_SIZE - 1) as usize] = true;
-                        mesh[x][y][(z as i32 - 1).clamp(0, CHUNK_SIZE as i32 - 1) as usize] = true;
-                    }
-                }
-            }
+    /// Uploads the chunks that the workers have finished meshing since last frame
+    fn receive_chunks(&mut self) {
+        for (coord, mesh_data) in self.streamer.receive(&mut self.world) {
+            self.dynamic_chunks.insert(
+                coord,
+                chunk_to_raw(&self.device, coord, mesh_data, self.camera.eye),
+            );
         }
+        self.drop_hidden_chunks();
     }
+}
+
+/// Leave one core for the main thread
+fn worker_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get().saturating_sub(1))
+        .unwrap_or(1)
+        .max(1)
+}
 
-    for x in 0..CHUNK_SIZE as usize {
-        let x_pos = (x as f32 * VOXEL_HALFWIDTH / 0.5) + origin_x;
-
-        for y in 0..CHUNK_SIZE as usize {
-            let y_pos = (y as f32 * VOXEL_HALFWIDTH / 0.5) + origin_y;
-
-            for z in 0..CHUNK_SIZE as usize {
-                let z_pos = (z as f32 * VOXEL_HALFWIDTH / 0.5) + origin_z;
-
-                if mesh[x][y][z] && (chunk.data[x][y][z] != 0) {
-                    let center = Pos3::new(
-                        x_pos + VOXEL_HALFWIDTH,
-                        y_pos + VOXEL_HALFWIDTH,
-                        z_pos + VOXEL_HALFWIDTH,
-                    );
-                    instance_data[chunk.data[x][y][z] as usize - 1].push(InstanceRaw {
-                        model: (Mat4::from_translation(center.to_vec())
-                            * Mat4::from_scale(VOXEL_HALFWIDTH))
-                        .into(),
-                    });
+/// Uploads a chunk's meshes to the GPU, with the translucent faces sorted for a camera at eye
+fn chunk_to_raw(
+    device: &wgpu::Device,
+    coord: ChunkCoord,
+    meshes: ChunkMesh,
+    eye: Pos3,
+) -> ChunkRender {
+    let ChunkMesh {
+        opaque,
+        cutout,
+        translucent,
+    } = meshes;
+    let name = format!("chunk {:?}", coord);
+    let indices = translucent.sorted_back_to_front(eye.into());
+    let translucent_mesh = upload_mesh(
+        device,
+        format!("{} translucent", name),
+        &translucent.vertices,
+        &indices,
+    );
+    ChunkRender {
+        opaque: upload_mesh(
+            device,
+            format!("{} opaque", name),
+            &opaque.vertices,
+            &opaque.indices,
+        ),
+        cutout: upload_mesh(
+            device,
+            format!("{} cutout", name),
+            &cutout.vertices,
+            &cutout.indices,
+        ),
+        translucent: translucent_mesh.map(|mesh| TranslucentMesh {
+            mesh,
+            data: translucent,
+            sorted_from: World::world_to_chunk(eye),
+        }),
+    }
+}
+
+/// Puts a mesh on the GPU, unless there's nothing in it to draw. The indices can be written to again later
+fn upload_mesh(
+    device: &wgpu::Device,
+    name: String,
+    vertices: &[VoxelVertex],
+    indices: &[u32],
+) -> Option<Mesh> {
+    if indices.is_empty() {
+        return None;
+    }
+    Some(Mesh {
+        vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        }),
+        index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+        }),
+        num_elements: indices.len() as u32,
+        material: 0,
+        name,
+    })
+}
+
+// Two triangles over one face of a block
+const CRACK_VERTICES: usize = 6;
+
+// Two ends for each of a cube's 12 edges
+const OUTLINE_VERTICES: usize = 24;
+
+/// Lines along the edges of a voxel, pushed out a little so they don't fight with its faces
+fn outline_vertices(voxel: VoxelPos) -> [LineVertex; OUTLINE_VERTICES] {
+    let size = VOXEL_HALFWIDTH * 2.0;
+    let margin = 0.02;
+    let corner = |x: usize, y: usize, z: usize| {
+        let offset =
+            |v: i32, c: usize| v as f32 * size + (c as f32 * (size + margin * 2.0)) - margin;
+        LineVertex {
+            position: [
+                offset(voxel[0], x),
+                offset(voxel[1], y),
+                offset(voxel[2], z),
+            ],
+        }
+    };
+    let mut vertices = [LineVertex { position: [0.0; 3] }; OUTLINE_VERTICES];
+    let mut i = 0;
+    // Every edge joins two corners that differ along one axis
+    for axis in 0..3 {
+        for a in 0..2 {
+            for b in 0..2 {
+                for end in 0..2 {
+                    let mut c = [0; 3];
+                    c[axis] = end;
+                    c[(axis + 1) % 3] = a;
+                    c[(axis + 2) % 3] = b;
+                    vertices[i] = corner(c[0], c[1], c[2]);
+                    i += 1;
                 }
             }
         }
     }
+    vertices
+}
 
-    for i in 0..instance_data.len() {
-        buffers.push(
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&i.to_string()),
-                contents: bytemuck::cast_slice(&instance_data[i]),
-                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            }),
-        );
-    }
-    ChunkRender {
-        instance_data,
-        buffers,
-    }
+/// Builds a pipeline for drawing things over the world, like the outline around the target block. They're
+/// blended over the blocks and hidden behind them, but don't hide anything themselves
+fn overlay_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): (&wgpu::ShaderModule, &wgpu::ShaderModule),
+    vertex: wgpu::VertexBufferLayout,
+    topology: wgpu::PrimitiveTopology,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("Overlay {:?} Pipeline", topology)),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[vertex],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                color_blend: wgpu::BlendState {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha_blend: wgpu::BlendState::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            // Setting this to true requires Features::DEPTH_CLAMPING
+            clamp_depth: false,
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
+
+/// Builds the pipeline that draws chunk meshes of one RenderMode. Translucent faces are blended over
+/// what's behind them, and don't write to the depth buffer so they can't hide each other.
+/// They aren't culled either, so the surface of water can be seen from under it
+fn voxel_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    render: RenderMode,
+) -> wgpu::RenderPipeline {
+    let translucent = render == RenderMode::Translucent;
+    let (color_blend, alpha_blend) = if translucent {
+        (
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        )
+    } else {
+        (wgpu::BlendState::REPLACE, wgpu::BlendState::REPLACE)
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("Render 3d {:?} Pipeline", render)),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[VoxelVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                alpha_blend,
+                color_blend,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: if translucent {
+                wgpu::CullMode::None
+            } else {
+                wgpu::CullMode::Back
+            },
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: !translucent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            // Setting this to true requires Features::DEPTH_CLAMPING
+            clamp_depth: false,
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
 }
@@ -1,38 +1,776 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::blocks::BlockRegistry;
+use crate::player::PlayerState;
 use crate::voxel::*;
-use crate::world_gen::WORLD_DIMS;
-
-pub fn save(chunks: &Vec<Chunk>) {
-    let mut file = std::fs::File::create("save.txt").expect("creation failed");
-    for chunk in chunks {
-        for x in 0..CHUNK_SIZE as usize {
-            for y in 0..CHUNK_SIZE as usize {
-                file.write_all(&chunk.data[x][y]).expect("write failed");
+use crate::world::{ChunkCoord, World};
+use crate::world_gen::{make_world, WorldGenParams};
+
+// Save files are laid out as (all numbers little endian):
+//   magic "MCWD", format version (u16), chunk size (u16), seed (u32),
+//   world gen params as JSON (u32 length + bytes),
+//   chunk count (u32), min and max chunk coordinates (3 i32s each),
+//   then for each chunk its coordinates (3 i32s), payload length (u32) and run length encoded voxels,
+//   and finally a CRC-32 of everything before it
+const MAGIC: &[u8; 4] = b"MCWD";
+// Chunks changed after the world file was written are added on to the end of a journal next to it:
+//   magic "MCWJ", format version (u16), the CRC-32 at the end of the world file it goes with (u32),
+//   then for each changed chunk its coordinates (3 i32s), payload length (u32), run length encoded
+//   voxels and a CRC-32 of the chunk's record
+const JOURNAL_MAGIC: &[u8; 4] = b"MCWJ";
+const JOURNAL_HEADER_LEN: usize = 4 + 2 + 4;
+pub const SAVE_VERSION: u16 = 1;
+// Every world gets its own folder in the save directory, holding these files
+pub const DEFAULT_SAVES_DIR: &str = "saves";
+const WORLD_FILE: &str = "world.dat";
+const JOURNAL_FILE: &str = "world.journal";
+const PLAYER_FILE: &str = "player.json";
+const META_FILE: &str = "meta.json";
+pub const DEFAULT_BACKUPS: usize = 3;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+impl World {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path.as_ref(), &self.encode()?, 0)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, blocks: &Arc<BlockRegistry>) -> Result<World> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        World::decode(&bytes, blocks).with_context(|| format!("could not load {}", path.display()))
+    }
+
+    /// Serializes every chunk the player has changed in the save format.
+    /// Everything else can be generated again from the seed and params
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        out.extend_from_slice(&(CHUNK_SIZE as u16).to_le_bytes());
+        out.extend_from_slice(&self.generator().seed().to_le_bytes());
+        let params = serde_json::to_vec(self.generator().params())?;
+        out.extend_from_slice(&(params.len() as u32).to_le_bytes());
+        out.extend_from_slice(&params);
+
+        // Sorted so the same world always saves to the same bytes
+        let mut coords: Vec<ChunkCoord> = self
+            .iter()
+            .map(|(coord, _)| *coord)
+            .filter(|coord| self.is_edited(*coord))
+            .collect();
+        coords.sort_unstable();
+        let (min, max) = chunk_bounds(&coords);
+        out.extend_from_slice(&(coords.len() as u32).to_le_bytes());
+        write_coord(&mut out, min);
+        write_coord(&mut out, max);
+
+        for coord in coords {
+            let payload = rle_encode(self.get(coord).unwrap());
+            write_coord(&mut out, coord);
+            out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            out.extend_from_slice(&payload);
+        }
+
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        Ok(out)
+    }
+
+    /// Rebuilds a world from bytes made by encode. Voxels are stored by id, so blocks
+    /// should be the registry the world was saved with
+    pub fn decode(bytes: &[u8], blocks: &Arc<BlockRegistry>) -> Result<World> {
+        World::decode_with_changes(bytes, blocks, HashMap::new())
+    }
+
+    // Like decode, but with newer copies of some chunks (from a journal) used in place of the saved ones
+    fn decode_with_changes(
+        bytes: &[u8],
+        blocks: &Arc<BlockRegistry>,
+        mut changes: HashMap<ChunkCoord, Chunk>,
+    ) -> Result<World> {
+        ensure!(bytes.len() >= MAGIC.len() + 4, "save file is too short");
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        let mut reader = Reader { bytes: body, at: 0 };
+        ensure!(reader.take(MAGIC.len())? == MAGIC, "not a world save file");
+        let version = reader.u16()?;
+        ensure!(
+            version == SAVE_VERSION,
+            "unsupported save version {} (expected {})",
+            version,
+            SAVE_VERSION
+        );
+        ensure!(
+            u32::from_le_bytes(checksum.try_into().unwrap()) == crc32(body),
+            "save file is corrupt (checksum mismatch)"
+        );
+        let chunk_size = reader.u16()? as usize;
+        ensure!(
+            chunk_size == CHUNK_SIZE,
+            "save uses chunks of size {}, expected {}",
+            chunk_size,
+            CHUNK_SIZE
+        );
+        let seed = reader.u32()?;
+        let params_len = reader.u32()? as usize;
+        let params: WorldGenParams = serde_json::from_slice(reader.take(params_len)?)
+            .context("could not read the world gen params")?;
+        let mut world = make_world(seed, params, Arc::clone(blocks));
+
+        let chunk_count = reader.u32()?;
+        let min = reader.coord()?;
+        let max = reader.coord()?;
+        for _ in 0..chunk_count {
+            let coord = reader.coord()?;
+            let in_bounds = (min.0..=max.0).contains(&coord.0)
+                && (min.1..=max.1).contains(&coord.1)
+                && (min.2..=max.2).contains(&coord.2);
+            ensure!(
+                in_bounds,
+                "chunk {:?} is outside of the saved bounds",
+                coord
+            );
+            let payload_len = reader.u32()? as usize;
+            let chunk = rle_decode(reader.take(payload_len)?)
+                .with_context(|| format!("chunk {:?} is corrupt", coord))?;
+            let chunk = changes.remove(&coord).unwrap_or(chunk);
+            world.insert(coord, chunk);
+            // Keep everything that was saved, since it may differ from freshly generated terrain
+            world.mark_edited(coord);
+        }
+        ensure!(reader.at == body.len(), "save file has trailing data");
+        // Chunks that were first changed after the world file was written
+        let mut changes: Vec<(ChunkCoord, Chunk)> = changes.into_iter().collect();
+        changes.sort_unstable_by_key(|(coord, _)| *coord);
+        for (coord, chunk) in changes {
+            world.insert(coord, chunk);
+            world.mark_edited(coord);
+        }
+        Ok(world)
+    }
+}
+
+/// What the world selection menu shows about a saved world
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldMeta {
+    pub name: String,
+    pub seed: u32,
+    pub last_played: u64, // Seconds since the unix epoch
+    pub play_time: f64,   // Seconds spent playing, over every session
+}
+
+impl WorldMeta {
+    pub fn new(name: &str, seed: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            seed,
+            last_played: now(),
+            play_time: 0.0,
+        }
+    }
+}
+
+/// Everything that gets loaded back out of a saved world
+pub struct SavedGame {
+    pub meta: WorldMeta,
+    pub world: World,
+    pub player: Option<PlayerState>, // Missing if the world was saved without a player
+    pub recovered_from: Option<PathBuf>, // The backup that was loaded, if the latest save was unreadable
+}
+
+/// The player file, tagged with the checksum of the world file it was saved alongside so the two can
+/// be matched back up when loading from backups
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    #[serde(default)]
+    world_checksum: Option<u32>,
+    #[serde(default)]
+    journal_len: u64, // How much of the world file's journal had been written
+    #[serde(flatten)]
+    player: PlayerState,
+}
+
+/// A directory of named worlds, each stored in a folder named after the world
+pub struct SaveDir {
+    root: PathBuf,
+    pub backups: usize, // How many older copies of each world and player file to keep around
+}
+
+impl SaveDir {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            backups: DEFAULT_BACKUPS,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Every saved world, most recently played first
+    pub fn list(&self) -> Result<Vec<WorldMeta>> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+        let mut worlds = vec![];
+        for entry in std::fs::read_dir(&self.root)
+            .with_context(|| format!("could not read {}", self.root.display()))?
+        {
+            let path = entry?.path();
+            // Anything without readable metadata isn't one of our worlds
+            if let Ok(meta) = read_meta(&path) {
+                worlds.push(meta);
+            }
+        }
+        worlds.sort_by(|a, b| {
+            b.last_played
+                .cmp(&a.last_played)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Ok(worlds)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.world_dir(name)
+            .map(|dir| dir.join(META_FILE).exists())
+            .unwrap_or(false)
+    }
+
+    /// First free name of the form "World N"
+    pub fn unused_name(&self) -> String {
+        (1..)
+            .map(|i| format!("World {}", i))
+            .find(|name| !self.world_dir(name).map(|dir| dir.exists()).unwrap_or(true))
+            .unwrap()
+    }
+
+    /// Saves a world under meta.name, marking it as played just now. Only the chunks changed since the
+    /// last save are written, added on to the end of the world file's journal. The whole world file is
+    /// only written again when there isn't one to add on to, or once the journal has grown bigger than
+    /// it. The player file is saved every time, but only makes a new backup along with the world file,
+    /// so each world backup keeps the journal and player that were saved with it
+    pub fn save(
+        &self,
+        meta: &mut WorldMeta,
+        world: &mut World,
+        player: &PlayerState,
+    ) -> Result<()> {
+        self.write(meta, world, player, false)
+    }
+
+    /// Saves a world like save, but always writes the whole world file again
+    pub fn save_snapshot(
+        &self,
+        meta: &mut WorldMeta,
+        world: &mut World,
+        player: &PlayerState,
+    ) -> Result<()> {
+        self.write(meta, world, player, true)
+    }
+
+    fn write(
+        &self,
+        meta: &mut WorldMeta,
+        world: &mut World,
+        player: &PlayerState,
+        snapshot: bool,
+    ) -> Result<()> {
+        let dir = self.world_dir(&meta.name)?;
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+        meta.seed = world.generator().seed();
+        meta.last_played = now();
+        let world_path = dir.join(WORLD_FILE);
+        let journal_path = dir.join(JOURNAL_FILE);
+
+        let mut dirty: Vec<ChunkCoord> = world.dirty_chunks().copied().collect();
+        dirty.sort_unstable();
+        let changes: Vec<u8> = dirty
+            .into_iter()
+            .filter_map(|coord| world.get(coord).map(|chunk| journal_record(coord, chunk)))
+            .flatten()
+            .collect();
+        // The journal can only be added on to if it's this world that was last saved there
+        let world_len = std::fs::metadata(&world_path).map_or(0, |m| m.len());
+        let journal = match world.saved_as() {
+            Some((checksum, journal_len))
+                if !snapshot
+                    && read_checksum(&world_path).ok() == Some(checksum)
+                    && journal_len + changes.len() as u64 <= world_len =>
+            {
+                Some((checksum, journal_len))
+            }
+            _ => None,
+        };
+        let (world_checksum, journal_len) = match journal {
+            Some((checksum, journal_len)) if changes.is_empty() => (checksum, journal_len),
+            Some((checksum, journal_len)) => (
+                checksum,
+                append_journal(&journal_path, checksum, journal_len, &changes)?,
+            ),
+            None => {
+                let bytes = world.encode()?;
+                write_atomic(&world_path, &bytes, self.backups)?;
+                // The old journal goes with the world file's newest backup
+                rotate_journal(&journal_path, self.backups)?;
+                (checksum(&bytes)?, 0)
+            }
+        };
+        world.clear_dirty();
+        world.set_saved_as(Some((world_checksum, journal_len)));
+
+        let player = SavedPlayer {
+            world_checksum: Some(world_checksum),
+            journal_len,
+            player: player.clone(),
+        };
+        write_atomic(
+            &dir.join(PLAYER_FILE),
+            &serde_json::to_vec_pretty(&player)?,
+            if journal.is_none() { self.backups } else { 0 },
+        )?;
+        write_meta(&dir, meta)
+    }
+
+    /// Loads a world, falling back on its backups (newest first) if the latest save is unreadable
+    pub fn load(&self, name: &str, blocks: &Arc<BlockRegistry>) -> Result<SavedGame> {
+        let dir = self.world_dir(name)?;
+        let meta = read_meta(&dir)?;
+        let world_path = dir.join(WORLD_FILE);
+        let journal_path = dir.join(JOURNAL_FILE);
+        let mut error = None;
+        for backup in 0..=self.backups {
+            let world_path = backup_path(&world_path, backup);
+            if backup > 0 && !world_path.exists() {
+                continue;
+            }
+            let loaded = std::fs::read(&world_path)
+                .with_context(|| format!("could not read {}", world_path.display()))
+                .and_then(|bytes| {
+                    let world_checksum = checksum(&bytes)?;
+                    // Along with the changes saved since, if its journal made it through
+                    let journal =
+                        std::fs::read(backup_path(&journal_path, backup)).unwrap_or_default();
+                    let (changes, journal_len) = read_journal(&journal, world_checksum);
+                    let world = World::decode_with_changes(&bytes, blocks, changes)
+                        .with_context(|| format!("could not load {}", world_path.display()))?;
+                    Ok((world, world_checksum, journal_len))
+                });
+            let (mut world, world_checksum, journal_len) = match loaded {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+            // Saves are only added on to the newest world file, so after falling back on a backup
+            // the next save writes the whole world again
+            if backup == 0 {
+                world.set_saved_as(Some((world_checksum, journal_len)));
+            }
+            // The player from the same save as the world, so they don't end up inside of blocks
+            let player = self.find_player(&dir, world_checksum, journal_len);
+            return Ok(SavedGame {
+                meta,
+                world,
+                player,
+                recovered_from: if backup > 0 { Some(world_path) } else { None },
+            });
+        }
+        Err(error.unwrap())
+    }
+
+    // The newest player file saved alongside the world file with this checksum (and this much of its
+    // journal), if there is one
+    fn find_player(
+        &self,
+        dir: &Path,
+        world_checksum: u32,
+        journal_len: u64,
+    ) -> Option<PlayerState> {
+        let player_path = dir.join(PLAYER_FILE);
+        (0..=self.backups)
+            .map(|backup| backup_path(&player_path, backup))
+            .filter_map(|path| std::fs::read(path).ok())
+            .filter_map(|json| serde_json::from_slice::<SavedPlayer>(&json).ok())
+            .find(|saved| {
+                saved.world_checksum == Some(world_checksum) && saved.journal_len == journal_len
+            })
+            .map(|saved| saved.player)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let dir = self.world_dir(name)?;
+        ensure!(self.exists(name), "there is no world named {:?}", name);
+        std::fs::remove_dir_all(&dir).with_context(|| format!("could not delete {}", dir.display()))
+    }
+
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        let dir = self.world_dir(name)?;
+        let new_dir = self.world_dir(new_name)?;
+        ensure!(self.exists(name), "there is no world named {:?}", name);
+        ensure!(
+            !new_dir.exists(),
+            "there is already a world named {:?}",
+            new_name
+        );
+        std::fs::rename(&dir, &new_dir)
+            .with_context(|| format!("could not rename {}", dir.display()))?;
+        let mut meta = read_meta(&new_dir)?;
+        meta.name = new_name.to_string();
+        write_meta(&new_dir, &meta)
+    }
+
+    // World names are used as folder names, so they can't be allowed to point anywhere else
+    fn world_dir(&self, name: &str) -> Result<PathBuf> {
+        ensure!(
+            !name.trim().is_empty()
+                && name != "."
+                && name != ".."
+                && !name.contains(&['/', '\\'][..]),
+            "{:?} isn't a valid world name",
+            name
+        );
+        Ok(self.root.join(name))
+    }
+}
+
+fn read_meta(dir: &Path) -> Result<WorldMeta> {
+    let path = dir.join(META_FILE);
+    let json =
+        std::fs::read(&path).with_context(|| format!("could not read {}", path.display()))?;
+    serde_json::from_slice(&json).with_context(|| format!("could not load {}", path.display()))
+}
+
+fn write_meta(dir: &Path, meta: &WorldMeta) -> Result<()> {
+    let path = dir.join(META_FILE);
+    write_atomic(&path, &serde_json::to_vec_pretty(meta)?, 0)
+}
+
+/// Writes to a temporary file first and then renames it over path, so a crash mid-write
+/// never leaves a half written save behind. The previous `backups` versions of the file
+/// are kept as path.1 (newest) to path.N (oldest)
+pub fn write_atomic(path: &Path, bytes: &[u8], backups: usize) -> Result<()> {
+    let temp_path = with_suffix(path, ".tmp");
+    {
+        let mut file = std::fs::File::create(&temp_path)
+            .with_context(|| format!("could not create {}", temp_path.display()))?;
+        file.write_all(bytes)
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("could not write {}", temp_path.display()))?;
+    }
+    if backups > 0 && path.exists() {
+        shift_backups(path, backups)?;
+        // Copied rather than moved, so there's never a moment without the file in place. The copy
+        // goes through a temporary file too, so a crash can't leave a half written backup behind
+        let backup = backup_path(path, 1);
+        let backup_temp = with_suffix(&backup, ".tmp");
+        std::fs::copy(path, &backup_temp)
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(&backup_temp)?
+                    .sync_all()
+            })
+            .with_context(|| format!("could not back up {}", path.display()))?;
+        std::fs::rename(&backup_temp, &backup)
+            .with_context(|| format!("could not back up {}", path.display()))?;
+    }
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("could not replace {}", path.display()))
+}
+
+/// Moves path.1 to path.2 and so on, dropping the oldest, which leaves room for a new path.1
+fn shift_backups(path: &Path, backups: usize) -> Result<()> {
+    for i in (1..backups).rev() {
+        let older = backup_path(path, i);
+        let newer = backup_path(path, i + 1);
+        // A gap stays a gap, so each backup keeps lining up with the files saved alongside it
+        let shifted = if older.exists() {
+            std::fs::rename(&older, &newer)
+        } else if newer.exists() {
+            std::fs::remove_file(&newer)
+        } else {
+            Ok(())
+        };
+        shifted.with_context(|| format!("could not rotate {}", older.display()))?;
+    }
+    Ok(())
+}
+
+/// Adds changed chunk records on to a journal whose first journal_len bytes are good, or starts a new
+/// journal for the world file with this checksum if journal_len is 0. Returns the journal's new length
+fn append_journal(
+    path: &Path,
+    world_checksum: u32,
+    journal_len: u64,
+    changes: &[u8],
+) -> Result<u64> {
+    if journal_len == 0 {
+        let mut bytes = journal_header(world_checksum);
+        bytes.extend_from_slice(changes);
+        write_atomic(path, &bytes, 0)?;
+        return Ok(bytes.len() as u64);
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut file| {
+            // Anything past the good part is left over from a save that was cut off
+            file.set_len(journal_len)?;
+            file.seek(SeekFrom::Start(journal_len))?;
+            file.write_all(changes)?;
+            file.sync_all()
+        })
+        .with_context(|| format!("could not write {}", path.display()))?;
+    Ok(journal_len + changes.len() as u64)
+}
+
+/// Moves the journal out of the way of a new world file, to path.1 alongside the backup of the world
+/// file it goes with
+fn rotate_journal(path: &Path, backups: usize) -> Result<()> {
+    let rotated = if backups == 0 {
+        match path.exists() {
+            true => std::fs::remove_file(path),
+            false => Ok(()),
+        }
+    } else {
+        shift_backups(path, backups)?;
+        match path.exists() {
+            true => std::fs::rename(path, backup_path(path, 1)),
+            false => Ok(()),
+        }
+    };
+    rotated.with_context(|| format!("could not rotate {}", path.display()))
+}
+
+fn journal_header(world_checksum: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(JOURNAL_HEADER_LEN);
+    out.extend_from_slice(JOURNAL_MAGIC);
+    out.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+    out.extend_from_slice(&world_checksum.to_le_bytes());
+    out
+}
+
+fn journal_record(coord: ChunkCoord, chunk: &Chunk) -> Vec<u8> {
+    let payload = rle_encode(chunk);
+    let mut out = Vec::new();
+    write_coord(&mut out, coord);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&payload);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// The newest copy of each chunk in a journal, if it goes with the world file with this checksum,
+/// and how many bytes of the journal were good. Reading stops at the first record that's cut off or
+/// corrupt, which is where a crash partway through a save would leave off
+fn read_journal(bytes: &[u8], world_checksum: u32) -> (HashMap<ChunkCoord, Chunk>, u64) {
+    let mut changes = HashMap::new();
+    if bytes.len() < JOURNAL_HEADER_LEN
+        || bytes[..JOURNAL_HEADER_LEN] != journal_header(world_checksum)[..]
+    {
+        return (changes, 0);
+    }
+    let mut reader = Reader {
+        bytes,
+        at: JOURNAL_HEADER_LEN,
+    };
+    loop {
+        let start = reader.at;
+        let record = (|| -> Result<(ChunkCoord, Chunk)> {
+            let coord = reader.coord()?;
+            let payload_len = reader.u32()? as usize;
+            let payload = reader.take(payload_len)?;
+            let end = reader.at;
+            ensure!(reader.u32()? == crc32(&bytes[start..end]), "corrupt record");
+            Ok((coord, rle_decode(payload)?))
+        })();
+        match record {
+            Ok((coord, chunk)) => {
+                changes.insert(coord, chunk);
             }
+            Err(_) => return (changes, start as u64),
+        }
+    }
+}
+
+/// The CRC-32 at the end of a world file's bytes
+fn checksum(bytes: &[u8]) -> Result<u32> {
+    ensure!(bytes.len() >= 4, "save file is too short");
+    Ok(u32::from_le_bytes(
+        bytes[bytes.len() - 4..].try_into().unwrap(),
+    ))
+}
+
+/// The CRC-32 at the end of a world file, without reading the rest of it
+fn read_checksum(path: &Path) -> Result<u32> {
+    let mut bytes = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::End(-4))?;
+            file.read_exact(&mut bytes)
+        })
+        .with_context(|| format!("could not read {}", path.display()))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// path.N, or just path for backup 0
+fn backup_path(path: &Path, backup: usize) -> PathBuf {
+    match backup {
+        0 => path.to_path_buf(),
+        _ => with_suffix(path, &format!(".{}", backup)),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+impl PlayerState {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path.as_ref(), &serde_json::to_vec_pretty(self)?, 0)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PlayerState> {
+        let path = path.as_ref();
+        let json =
+            std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        serde_json::from_slice(&json).with_context(|| format!("could not load {}", path.display()))
+    }
+}
+
+fn write_coord(out: &mut Vec<u8>, coord: ChunkCoord) {
+    for c in [coord.0, coord.1, coord.2].iter() {
+        out.extend_from_slice(&c.to_le_bytes());
+    }
+}
+
+fn chunk_bounds(coords: &[ChunkCoord]) -> (ChunkCoord, ChunkCoord) {
+    let mut bounds = match coords.first() {
+        Some(first) => (*first, *first),
+        None => return ((0, 0, 0), (0, 0, 0)),
+    };
+    for (x, y, z) in coords {
+        let (min, max) = &mut bounds;
+        *min = (min.0.min(*x), min.1.min(*y), min.2.min(*z));
+        *max = (max.0.max(*x), max.1.max(*y), max.2.max(*z));
+    }
+    bounds
+}
+
+/// Stores the voxels (in x, y, z order) as pairs of run length and voxel
+pub fn rle_encode(chunk: &Chunk) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut voxels = chunk.data.iter().flatten().flatten();
+    let mut current = match voxels.next() {
+        Some(voxel) => *voxel,
+        None => return out,
+    };
+    let mut run: u8 = 1;
+    for voxel in voxels {
+        if *voxel == current && run < u8::MAX {
+            run += 1;
+        } else {
+            out.push(run);
+            out.push(current);
+            current = *voxel;
+            run = 1;
         }
     }
-    println!("sucessful save");
+    out.push(run);
+    out.push(current);
+    out
 }
 
-pub fn load() -> Vec<Chunk> {
-    let mut f = File::open("save.txt").expect("no file found");
-    let metadata = std::fs::metadata("save.txt").expect("unable to read metadata");
-    let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer).expect("buffer overflow");
-    let mut chunks: Vec<Chunk> = Vec::with_capacity(WORLD_DIMS.0 * WORLD_DIMS.1 * WORLD_DIMS.2);
-    let mut data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE] =
-        [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
-    for i in 0..buffer.len() {
-        let x = (i / (CHUNK_SIZE.pow(2))) % CHUNK_SIZE;
-        let y = (i / CHUNK_SIZE) % CHUNK_SIZE;
-        let z = i % CHUNK_SIZE;
-        data[x][y][z] = buffer[i];
-        if (i + 1) % CHUNK_SIZE.pow(3) == 0 {
-            chunks.push(Chunk { data });
+/// Reverses rle_encode. The runs have to add up to exactly one chunk's worth of voxels
+pub fn rle_decode(bytes: &[u8]) -> Result<Chunk> {
+    let pairs = bytes.chunks_exact(2);
+    ensure!(
+        pairs.remainder().is_empty(),
+        "run length data has an odd length"
+    );
+    let mut data = [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+    let mut i = 0;
+    for pair in pairs {
+        let (run, voxel) = (pair[0] as usize, pair[1]);
+        ensure!(
+            run > 0 && i + run <= CHUNK_VOLUME,
+            "run length data doesn't fit in a chunk"
+        );
+        for j in i..i + run {
+            let x = j / (CHUNK_SIZE * CHUNK_SIZE);
+            let y = (j / CHUNK_SIZE) % CHUNK_SIZE;
+            let z = j % CHUNK_SIZE;
+            data[x][y][z] = voxel;
         }
+        i += run;
+    }
+    ensure!(i == CHUNK_VOLUME, "run length data is missing voxels");
+    Ok(Chunk { data })
+}
+
+/// The usual CRC-32 (same as zlib and png)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.at + len <= self.bytes.len(),
+            "save file ended unexpectedly"
+        );
+        let taken = &self.bytes[self.at..self.at + len];
+        self.at += len;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn coord(&mut self) -> Result<ChunkCoord> {
+        Ok((self.i32()?, self.i32()?, self.i32()?))
     }
-    println!("succesful load");
-    chunks
 }
@@ -0,0 +1,245 @@
+use crate::jobs::{ChunkState, Job, JobPool, JobResult};
+use crate::mesher::{ChunkMesh, Face};
+use crate::world::{ChunkCoord, World};
+use crate::world_gen::{LOAD_RADIUS, UNLOAD_RADIUS};
+use std::collections::{HashMap, HashSet};
+
+/// Keeps track of how far along every chunk around the player is in being loaded, and hands the
+/// slow parts (generating, lighting and meshing them) to a pool of worker threads.
+/// Doesn't touch the GPU, the meshes that come back are for the caller to upload
+pub struct ChunkStreamer {
+    pub load_radius: (i32, i32), // (horizontal, vertical) radius of chunks generated around the player
+    pub unload_radius: (i32, i32), // chunks further away than this get unloaded
+    center: ChunkCoord,
+    jobs: JobPool,
+    in_flight: usize,
+    states: HashMap<ChunkCoord, ChunkState>,
+    epoch: u64, // bumped whenever the world is replaced so old jobs get ignored
+    meshes: HashMap<ChunkCoord, u64>, // The newest mesh asked for of each chunk, older ones get ignored
+    mesh_count: u64,
+}
+
+impl ChunkStreamer {
+    pub fn new(worker_count: usize) -> Self {
+        Self {
+            load_radius: LOAD_RADIUS,
+            unload_radius: UNLOAD_RADIUS,
+            center: (0, 0, 0),
+            jobs: JobPool::new(worker_count),
+            in_flight: 0,
+            states: HashMap::new(),
+            epoch: 0,
+            meshes: HashMap::new(),
+            mesh_count: 0,
+        }
+    }
+
+    /// How far along the chunk at coord is in being loaded
+    pub fn state(&self, coord: ChunkCoord) -> ChunkState {
+        *self.states.get(&coord).unwrap_or(&ChunkState::Unloaded)
+    }
+
+    /// The chunk that chunks are being streamed in around
+    pub fn center(&self) -> ChunkCoord {
+        self.center
+    }
+
+    /// How many jobs haven't come back yet, counting ones that will be thrown away when they do
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Starts over with a different world (e.g. one loaded from a save). The chunks it already has
+    /// are kept as they are, only the missing ones get generated
+    pub fn reset(&mut self, world: &mut World, center: ChunkCoord) {
+        self.epoch += 1;
+        self.states = world
+            .iter()
+            .map(|(coord, _)| (*coord, ChunkState::Generated))
+            .collect();
+        self.stream(world, center);
+    }
+
+    /// Queues up generation for chunks inside the load radius around center, and drops the ones
+    /// outside the unload radius
+    pub fn stream(&mut self, world: &mut World, center: ChunkCoord) {
+        self.center = center;
+        let (unload_h, unload_v) = self.unload_radius;
+        let outside_unload = |(x, y, z): ChunkCoord| {
+            (x - center.0).abs() > unload_h
+                || (y - center.1).abs() > unload_v
+                || (z - center.2).abs() > unload_h
+        };
+        world.unload_where(outside_unload);
+        self.states.retain(|coord, state| {
+            if !world.contains(*coord) {
+                // Unloaded, or still being generated far away (which gets thrown out when it arrives)
+                *state == ChunkState::Generating
+            } else {
+                // Only kept around because it was edited, so it doesn't get drawn
+                if outside_unload(*coord) {
+                    *state = ChunkState::Generated;
+                }
+                true
+            }
+        });
+        let states = &self.states;
+        self.meshes.retain(|coord, _| states.contains_key(coord));
+
+        let (x, y, z) = center;
+        let (load_h, load_v) = self.load_radius;
+        for world_x in (x - load_h)..=(x + load_h) {
+            for world_y in (y - load_v)..=(y + load_v) {
+                for world_z in (z - load_h)..=(z + load_h) {
+                    let coord = (world_x, world_y, world_z);
+                    match self.state(coord) {
+                        ChunkState::Unloaded if world.contains(coord) => {
+                            self.states.insert(coord, ChunkState::Generated);
+                            self.mesh_when_settled(world, coord);
+                        }
+                        ChunkState::Unloaded => {
+                            self.submit(Job::Generate(
+                                self.epoch,
+                                coord,
+                                world.generator().clone(),
+                            ));
+                            self.states.insert(coord, ChunkState::Generating);
+                        }
+                        ChunkState::Generated => self.mesh_when_settled(world, coord),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Picks up the chunks that the workers have finished since the last call, putting the new
+    /// terrain in the world. Returns the meshes to draw, which replace any the chunks had before
+    pub fn receive(&mut self, world: &mut World) -> Vec<(ChunkCoord, ChunkMesh)> {
+        let mut meshes = vec![];
+        // Chunks that were meshed before a chunk next to them came in. They're all meshed again at the end,
+        // along with any chunks the new chunks' light spread into
+        let mut stale = HashSet::new();
+        let finished: Vec<JobResult> = self.jobs.finished().collect();
+        self.in_flight -= finished.len();
+        for result in finished {
+            match result {
+                JobResult::Generated(epoch, coord, chunk, light) => {
+                    if epoch != self.epoch || self.state(coord) != ChunkState::Generating {
+                        continue;
+                    }
+                    if !self.in_radius(coord, self.unload_radius) {
+                        // The player moved away while it was being generated
+                        self.states.remove(&coord);
+                        continue;
+                    }
+                    // A chunk that got into the world some other way in the meantime (e.g. an edit)
+                    // is newer than the generated terrain
+                    if !world.contains(coord) {
+                        world.insert_lit(coord, *chunk, *light);
+                    }
+                    self.states.insert(coord, ChunkState::Generated);
+                    self.mesh_when_settled(world, coord);
+                    for face in Face::ALL.iter() {
+                        let neighbor = World::neighbor_coord(coord, *face);
+                        match self.state(neighbor) {
+                            ChunkState::Generated => self.mesh_when_settled(world, neighbor),
+                            // Meshed without this chunk, so its border faces are out of date
+                            ChunkState::Meshing | ChunkState::Ready => {
+                                stale.insert(neighbor);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                JobResult::Meshed(epoch, coord, generation, mesh) => {
+                    if epoch != self.epoch
+                        || self.state(coord) != ChunkState::Meshing
+                        || self.meshes.get(&coord) != Some(&generation)
+                    {
+                        continue;
+                    }
+                    if !self.in_radius(coord, self.unload_radius) {
+                        // Only kept around because it was edited, there's no need to draw it
+                        self.states.insert(coord, ChunkState::Generated);
+                        continue;
+                    }
+                    self.states.insert(coord, ChunkState::Ready);
+                    meshes.push((coord, mesh));
+                }
+            }
+        }
+        stale.extend(world.take_relit());
+        self.request_meshes(world, stale);
+        meshes
+    }
+
+    /// Meshes the chunks again if they're already being drawn (or about to be)
+    pub fn request_meshes(&mut self, world: &World, coords: HashSet<ChunkCoord>) {
+        for coord in coords {
+            if let ChunkState::Meshing | ChunkState::Ready = self.state(coord) {
+                self.request_mesh(world, coord);
+            }
+        }
+    }
+
+    /// Records that the chunk was just meshed on the main thread, so any mesh job still in flight
+    /// for it gets ignored
+    pub fn meshed(&mut self, coord: ChunkCoord) {
+        self.next_mesh(coord);
+        self.states.insert(coord, ChunkState::Ready);
+    }
+
+    fn submit(&mut self, job: Job) {
+        self.jobs.submit(job);
+        self.in_flight += 1;
+    }
+
+    fn in_radius(&self, (x, y, z): ChunkCoord, (horizontal, vertical): (i32, i32)) -> bool {
+        let center = self.center;
+        (x - center.0).abs() <= horizontal
+            && (y - center.1).abs() <= vertical
+            && (z - center.2).abs() <= horizontal
+    }
+
+    /// Are all of the chunk's neighbours that are going to be loaded in?
+    /// Meshing before then would draw border faces that the neighbours will end up hiding
+    fn neighbors_settled(&self, world: &World, coord: ChunkCoord) -> bool {
+        Face::ALL.iter().all(|face| {
+            let neighbor = World::neighbor_coord(coord, *face);
+            world.contains(neighbor) || !self.in_radius(neighbor, self.load_radius)
+        })
+    }
+
+    /// Meshes a generated chunk once its neighbours are in
+    fn mesh_when_settled(&mut self, world: &World, coord: ChunkCoord) {
+        if self.state(coord) == ChunkState::Generated
+            && self.in_radius(coord, self.load_radius)
+            && self.neighbors_settled(world, coord)
+        {
+            self.request_mesh(world, coord);
+        }
+    }
+
+    /// Sends a copy of a loaded chunk (and its neighbours) off to be meshed
+    fn request_mesh(&mut self, world: &World, coord: ChunkCoord) {
+        if let Some(neighborhood) = world.neighborhood(coord) {
+            let generation = self.next_mesh(coord);
+            self.submit(Job::Mesh(
+                self.epoch,
+                coord,
+                generation,
+                Box::new(neighborhood),
+                world.blocks().clone(),
+            ));
+            self.states.insert(coord, ChunkState::Meshing);
+        }
+    }
+
+    /// Numbers a new mesh of the chunk, making it the only one that gets drawn
+    fn next_mesh(&mut self, coord: ChunkCoord) -> u64 {
+        self.mesh_count += 1;
+        self.meshes.insert(coord, self.mesh_count);
+        self.mesh_count
+    }
+}
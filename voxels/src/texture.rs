@@ -24,6 +24,94 @@ impl Texture {
         Self::from_image(device, queue, &img, label)
     }
 
+    /// Loads images into the layers of one texture array, in order. They all have to be the same size
+    pub fn load_array<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[P],
+        label: &str,
+    ) -> Result<Self> {
+        let images = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                image::open(path).with_context(|| format!("could not load {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let dimensions = images
+            .first()
+            .context("a texture array needs at least one image")?
+            .dimensions();
+        for (image, path) in images.iter().zip(paths) {
+            ensure!(
+                image.dimensions() == dimensions,
+                "{} is {:?} but the other layers of {} are {:?}",
+                path.as_ref().display(),
+                image.dimensions(),
+                label,
+                dimensions
+            );
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: dimensions.0,
+                height: dimensions.1,
+                depth: images.len() as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        for (layer, image) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                &image.to_rgba8(),
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * dimensions.0,
+                    rows_per_image: dimensions.1,
+                },
+                wgpu::Extent3d {
+                    width: dimensions.0,
+                    height: dimensions.1,
+                    depth: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         sc_desc: &wgpu::SwapChainDescriptor,
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in vec2 v_tex_cell;
+layout(location=2) in float v_ao;
+layout(location=3) in vec2 v_light;
+layout(location=4) flat in uint v_layer;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2DArray t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+void main() {
+    // Merged faces cover several voxels, so repeat the face's cell of its block texture once per voxel.
+    // Staying just inside of the cell keeps the neighbouring cells from bleeding in
+    vec2 in_cell = clamp(fract(v_tex_coords), 0.01, 0.99);
+    vec4 color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_cell + in_cell / 3.0, float(v_layer)));
+    // Each level of light is a bit dimmer than the one above it, whichever of sky or block light is
+    // brighter wins. Nothing is ever completely black
+    float light = max(v_light.x, v_light.y);
+    float brightness = max(pow(0.8, 15.0 * (1.0 - light)), 0.05);
+    // Darken corners that are boxed in by other voxels
+    f_color = vec4(color.rgb * brightness * v_ao, color.a);
+}
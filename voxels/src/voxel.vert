@@ -0,0 +1,28 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=2) in vec2 a_tex_cell;
+layout(location=3) in float a_ao;
+layout(location=4) in vec2 a_light;
+layout(location=5) in uint a_layer;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec2 v_tex_cell;
+layout(location=2) out float v_ao;
+layout(location=3) out vec2 v_light;
+layout(location=4) flat out uint v_layer;
+
+layout(set=1, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_tex_cell = a_tex_cell;
+    v_ao = a_ao;
+    v_light = a_light;
+    v_layer = a_layer;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
@@ -0,0 +1,287 @@
+use crate::blocks::BlockRegistry;
+use crate::light::{split, LightChunk, Lighting, VoxelPos};
+use crate::mesher::{Face, Neighborhood};
+use crate::voxel::*;
+use crate::world_gen::WorldGen;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+type Pos3 = cgmath::Point3<f32>;
+
+/// Position of a chunk in the world, measured in chunks. Can be negative.
+pub type ChunkCoord = (i32, i32, i32);
+/// Position of a voxel inside of a chunk
+pub type VoxelCoord = (usize, usize, usize);
+
+/// Sparse storage for every chunk that is currently loaded.
+/// Chunks that aren't in the map are treated as unloaded (and empty).
+pub struct World {
+    chunks: HashMap<ChunkCoord, Chunk>,
+    // Chunks that were changed by the player, these are never unloaded
+    edited: HashSet<ChunkCoord>,
+    // Chunks that were changed since the world was last saved
+    dirty: HashSet<ChunkCoord>,
+    // Checksum of the world file this was last saved to or loaded from, and how many bytes of that
+    // file's journal of changes were written by then
+    saved_as: Option<(u32, u64)>,
+    lighting: Lighting,
+    generator: Arc<WorldGen>,
+}
+
+impl World {
+    pub fn new(generator: WorldGen) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            edited: HashSet::new(),
+            dirty: HashSet::new(),
+            saved_as: None,
+            lighting: Lighting::new(generator.sky_layer()),
+            generator: Arc::new(generator),
+        }
+    }
+
+    /// The generator is shared so that chunks can be generated on other threads
+    pub fn generator(&self) -> &Arc<WorldGen> {
+        &self.generator
+    }
+
+    /// The blocks that the world is made of
+    pub fn blocks(&self) -> &Arc<BlockRegistry> {
+        self.generator.blocks()
+    }
+
+    pub fn get(&self, coord: ChunkCoord) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    /// Changes made through this don't update the light, use set_voxel for that
+    pub fn get_mut(&mut self, coord: ChunkCoord) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&coord)
+    }
+
+    pub fn contains(&self, coord: ChunkCoord) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    /// Adds a chunk to the world and lights it, returning the chunk that was there before (if any)
+    pub fn insert(&mut self, coord: ChunkCoord, chunk: Chunk) -> Option<Chunk> {
+        let old = self.chunks.insert(coord, chunk);
+        self.lighting
+            .light_chunk(&self.chunks, self.generator.blocks(), coord);
+        old
+    }
+
+    /// Adds a chunk along with its light from light::light_alone, returning the chunk that was there
+    /// before (if any). Only the light crossing its borders is worked out here
+    pub fn insert_lit(
+        &mut self,
+        coord: ChunkCoord,
+        chunk: Chunk,
+        light: LightChunk,
+    ) -> Option<Chunk> {
+        let old = self.chunks.insert(coord, chunk);
+        self.lighting
+            .add_lit_chunk(&self.chunks, self.generator.blocks(), coord, light);
+        old
+    }
+
+    pub fn remove(&mut self, coord: ChunkCoord) -> Option<Chunk> {
+        self.lighting.remove(coord);
+        self.chunks.remove(&coord)
+    }
+
+    /// Returns the chunk at coord, only calling make_chunk if it isn't loaded yet
+    pub fn get_or_insert_with<F: FnOnce() -> Chunk>(
+        &mut self,
+        coord: ChunkCoord,
+        make_chunk: F,
+    ) -> &mut Chunk {
+        if !self.contains(coord) {
+            self.insert(coord, make_chunk());
+        }
+        self.chunks.get_mut(&coord).unwrap()
+    }
+
+    /// Returns the chunk at coord, generating it first if it isn't loaded
+    pub fn load_chunk(&mut self, coord: ChunkCoord) -> &mut Chunk {
+        let generator = Arc::clone(&self.generator);
+        self.get_or_insert_with(coord, || generator.generate_chunk(coord))
+    }
+
+    /// Light levels of a loaded chunk
+    pub fn light(&self, coord: ChunkCoord) -> Option<&LightChunk> {
+        self.lighting.get(coord)
+    }
+
+    /// (sky, block) light at a world position. None if the chunk isn't loaded
+    pub fn light_at(&self, pos: Pos3) -> Option<(u8, u8)> {
+        let (coord, voxel) = World::world_to_chunk(pos);
+        self.light(coord)
+            .map(|light| (light.sky(voxel), light.block(voxel)))
+    }
+
+    /// Chunks whose light has changed since this was last called, which need to be meshed again
+    pub fn take_relit(&mut self) -> HashSet<ChunkCoord> {
+        self.lighting.take_relit()
+    }
+
+    /// The chunk next to coord in the direction face points
+    pub fn neighbor_coord(coord: ChunkCoord, face: Face) -> ChunkCoord {
+        let (x, y, z) = face.normal();
+        (coord.0 + x, coord.1 + y, coord.2 + z)
+    }
+
+    /// Copies the chunk at coord and its neighbours for meshing. None if the chunk isn't loaded
+    pub fn neighborhood(&self, coord: ChunkCoord) -> Option<Neighborhood> {
+        let chunk = self.get(coord)?.clone();
+        let light = self.light(coord).cloned().unwrap_or_default();
+        let mut neighbors: [Option<Chunk>; 6] = Default::default();
+        let mut neighbor_light: [Option<LightChunk>; 6] = Default::default();
+        for (i, face) in Face::ALL.iter().enumerate() {
+            let neighbor = World::neighbor_coord(coord, *face);
+            neighbors[i] = self.get(neighbor).cloned();
+            neighbor_light[i] = self.light(neighbor).cloned();
+        }
+        Some(Neighborhood {
+            chunk,
+            neighbors,
+            light,
+            neighbor_light,
+        })
+    }
+
+    /// Drops every chunk that unload returns true for, unless it has been edited
+    pub fn unload_where<F: Fn(ChunkCoord) -> bool>(&mut self, unload: F) {
+        let edited = &self.edited;
+        self.chunks
+            .retain(|coord, _| edited.contains(coord) || !unload(*coord));
+        let chunks = &self.chunks;
+        self.lighting.retain(|coord| chunks.contains_key(&coord));
+    }
+
+    /// Marks a chunk as edited so it stays loaded
+    pub fn mark_edited(&mut self, coord: ChunkCoord) {
+        self.edited.insert(coord);
+    }
+
+    pub fn is_edited(&self, coord: ChunkCoord) -> bool {
+        self.edited.contains(&coord)
+    }
+
+    /// Chunks that have changed since the last save
+    pub fn dirty_chunks(&self) -> impl Iterator<Item = &ChunkCoord> {
+        self.dirty.iter()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Call once the world has been saved
+    pub fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    pub(crate) fn saved_as(&self) -> Option<(u32, u64)> {
+        self.saved_as
+    }
+
+    pub(crate) fn set_saved_as(&mut self, saved_as: Option<(u32, u64)>) {
+        self.saved_as = saved_as;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ChunkCoord, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Voxel at a world position. None if the chunk isn't loaded
+    pub fn voxel(&self, pos: Pos3) -> Option<u8> {
+        let (coord, (x, y, z)) = World::world_to_chunk(pos);
+        self.get(coord).map(|chunk| chunk.data[x][y][z])
+    }
+
+    /// Voxel at a position counted in voxels from the world origin. None if the chunk isn't loaded
+    pub fn voxel_at(&self, pos: VoxelPos) -> Option<u8> {
+        let (coord, (x, y, z)) = split(pos);
+        self.get(coord).map(|chunk| chunk.data[x][y][z])
+    }
+
+    /// Sets the voxel at a world position and updates the light around it.
+    /// Returns false if the chunk isn't loaded
+    pub fn set_voxel(&mut self, pos: Pos3, voxel: u8) -> bool {
+        let (coord, (x, y, z)) = World::world_to_chunk(pos);
+        match self.chunks.get_mut(&coord) {
+            Some(chunk) => {
+                chunk.data[x][y][z] = voxel;
+                self.edited.insert(coord);
+                self.dirty.insert(coord);
+                let size = CHUNK_SIZE as i32;
+                let voxel_pos = [
+                    coord.0 * size + x as i32,
+                    coord.1 * size + y as i32,
+                    coord.2 * size + z as i32,
+                ];
+                self.lighting
+                    .update_voxel(&self.chunks, self.generator.blocks(), voxel_pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Converts a world position into the chunk that holds it and the voxel inside that chunk
+    pub fn world_to_chunk(coords: Pos3) -> (ChunkCoord, VoxelCoord) {
+        let vox_scale = VOXEL_HALFWIDTH * 2.0;
+        // Which voxel are we in, counting from the world origin
+        let x = (coords.x / vox_scale).floor() as i32;
+        let y = (coords.y / vox_scale).floor() as i32;
+        let z = (coords.z / vox_scale).floor() as i32;
+        let size = CHUNK_SIZE as i32;
+        // Euclidean division keeps negative coordinates in the right chunk
+        let chunk = (x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        let voxel = (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        );
+        (chunk, voxel)
+    }
+
+    /// Which voxel a world position is in, counting from the world origin
+    pub fn world_to_voxel(coords: Pos3) -> VoxelPos {
+        let vox_scale = VOXEL_HALFWIDTH * 2.0;
+        [
+            (coords.x / vox_scale).floor() as i32,
+            (coords.y / vox_scale).floor() as i32,
+            (coords.z / vox_scale).floor() as i32,
+        ]
+    }
+
+    /// World position of the center of a voxel
+    pub fn voxel_center([x, y, z]: VoxelPos) -> Pos3 {
+        let vox_scale = VOXEL_HALFWIDTH * 2.0;
+        Pos3::new(
+            (x as f32 + 0.5) * vox_scale,
+            (y as f32 + 0.5) * vox_scale,
+            (z as f32 + 0.5) * vox_scale,
+        )
+    }
+
+    /// World position of the minimum corner of a chunk
+    pub fn chunk_to_world(coord: ChunkCoord) -> Pos3 {
+        let chunk_scale = CHUNK_SIZE as f32 * VOXEL_HALFWIDTH * 2.0;
+        Pos3::new(
+            coord.0 as f32 * chunk_scale,
+            coord.1 as f32 * chunk_scale,
+            coord.2 as f32 * chunk_scale,
+        )
+    }
+}
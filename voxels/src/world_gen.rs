@@ -1,61 +1,201 @@
+use crate::blocks::{BlockRegistry, AIR};
 use crate::voxel::*;
+use crate::world::{ChunkCoord, World};
 use rand;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 extern crate noise;
 use noise::{Add, NoiseFn, Perlin, Seedable, Turbulence};
 
-pub const WORLD_DIMS: (usize, usize, usize) = (10, 5, 10); // The number of chunks that you want to load in 3D space
-pub const WORLD_MAX: usize = WORLD_DIMS.0 * WORLD_DIMS.1 * WORLD_DIMS.2;
-pub const RENDER_RADIUS: (usize, usize) = (1, 1);
-
-const PERLIN_STEP_2D: f64 = 0.03;
-const PERLIN_STEP_TOP: f64 = 0.15;
-const PERLIN_STEP_MID: f64 = 0.07;
+pub const LOAD_RADIUS: (i32, i32) = (2, 1); // Default (horizontal, vertical) radius of chunks streamed in around the player
+pub const UNLOAD_RADIUS: (i32, i32) = (3, 2); // Default radius past which chunks are streamed back out
 
 pub type Pos3 = cgmath::Point3<f32>;
 
-pub fn make_world() -> Vec<Chunk> {
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-    // Iterate through the world chunks, and in each chunk place a random voxel
-    let mut chunks: Vec<Chunk> = Vec::new();
-
-    let seed: u32 = rng.gen();
-    let noise_1 = Perlin::new().set_seed(seed);
-    let turbulence = Turbulence::new(noise_1);
-    let perlin3 = Perlin::new().set_seed(seed);
-
-    let perlin2: Add<[f64; 2]> = Add::new(&turbulence, &noise_1);
-
-    for cx in 0..WORLD_DIMS.0 {
-        for cy in 0..WORLD_DIMS.1 {
-            for cz in 0..WORLD_DIMS.2 {
-                if cy == 0 {
-                    chunks.push(make_bottom_layer(
-                        (cx as f64, cy as f64, cz as f64),
-                        &perlin3,
-                    ));
-                } else if cy >= (WORLD_DIMS.1 - 1) {
-                    chunks.push(make_air_layer());
-                } else if cy >= (WORLD_DIMS.1 - 2) {
-                    chunks.push(make_top_layer(
-                        (cx as f64, cy as f64, cz as f64),
-                        &perlin2,
-                        &perlin3,
-                    ));
-                } else {
-                    chunks.push(make_mid_layer((cx as f64, cy as f64, cz as f64), &perlin3));
-                }
-            }
+/// A block (by name) that's placed wherever the 3D noise is at most max_noise (and above the band before it)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialBand {
+    pub max_noise: f64,
+    pub material: String,
+}
+
+fn band(max_noise: f64, material: &str) -> MaterialBand {
+    MaterialBand {
+        max_noise,
+        material: material.to_string(),
+    }
+}
+
+/// Everything besides the seed that decides what a world looks like
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldGenParams {
+    pub perlin_step_2d: f64,  // Frequency of the surface height noise
+    pub perlin_step_top: f64, // Frequency of the rock/ore noise under the dirt
+    pub perlin_step_mid: f64, // Frequency of the cave/ore noise in the middle and bottom layers
+    pub world_height: i32, // Number of chunk layers with terrain. The surface is in the second highest
+    pub dirt_depth: f64,   // How many voxels of dirt sit under the grass
+    pub mid_bands: Vec<MaterialBand>,
+    pub bottom_bands: Vec<MaterialBand>,
+}
+
+impl Default for WorldGenParams {
+    fn default() -> Self {
+        Self {
+            perlin_step_2d: 0.03,
+            perlin_step_top: 0.15,
+            perlin_step_mid: 0.07,
+            world_height: 5,
+            dirt_depth: 3.0,
+            mid_bands: vec![
+                band(0.3, "rock"),
+                band(0.33, "iron"),
+                band(0.44, "rock"),
+                band(0.55, "air"),
+                band(0.7, "rock"),
+                band(0.73, "gold"),
+                band(1.0, "rock"),
+            ],
+            bottom_bands: vec![
+                band(0.01, "bedrock"),
+                band(0.3, "rock"),
+                band(0.32, "iron"),
+                band(0.44, "rock"),
+                band(0.55, "air"),
+                band(0.7, "rock"),
+                band(0.72, "gold"),
+                band(0.98, "rock"),
+                band(1.0, "bedrock"),
+            ],
+        }
+    }
+}
+
+/// Ids of the blocks the terrain is built out of, looked up by name in the block registry.
+/// Any that are missing from it generate as air
+#[derive(Copy, Clone, Debug)]
+struct TerrainBlocks {
+    grass: u8,
+    dirt: u8,
+    rock: u8,
+    bedrock: u8,
+}
+
+impl TerrainBlocks {
+    fn new(blocks: &BlockRegistry) -> Self {
+        let id = |name| blocks.id(name).unwrap_or(AIR);
+        Self {
+            grass: id("grass"),
+            dirt: id("dirt"),
+            rock: id("rock"),
+            bedrock: id("bedrock"),
+        }
+    }
+}
+
+/// Holds the noise functions for a world so chunks can be generated whenever they're needed.
+/// The same seed and params always generate the same chunks
+pub struct WorldGen {
+    seed: u32,
+    params: WorldGenParams,
+    blocks: Arc<BlockRegistry>,
+    terrain: TerrainBlocks,
+    mid_bands: Vec<(f64, u8)>, // The params' bands with their blocks looked up
+    bottom_bands: Vec<(f64, u8)>,
+    noise_1: Perlin,
+    turbulence: Turbulence<Perlin>,
+    perlin3: Perlin,
+}
+
+impl WorldGen {
+    pub fn new(seed: u32, params: WorldGenParams, blocks: Arc<BlockRegistry>) -> Self {
+        let noise_1 = Perlin::new().set_seed(seed);
+        let turbulence = Turbulence::new(noise_1);
+        let perlin3 = Perlin::new().set_seed(seed);
+        // Blocks that are missing from the registry generate as air, like the rest of the terrain
+        let resolve = |bands: &[MaterialBand]| {
+            bands
+                .iter()
+                .map(|band| (band.max_noise, blocks.id(&band.material).unwrap_or(AIR)))
+                .collect()
+        };
+        Self {
+            seed,
+            mid_bands: resolve(&params.mid_bands),
+            bottom_bands: resolve(&params.bottom_bands),
+            params,
+            terrain: TerrainBlocks::new(&blocks),
+            blocks,
+            noise_1,
+            turbulence,
+            perlin3,
+        }
+    }
+
+    /// The blocks that the world is made of
+    pub fn blocks(&self) -> &Arc<BlockRegistry> {
+        &self.blocks
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn params(&self) -> &WorldGenParams {
+        &self.params
+    }
+
+    /// The lowest layer of chunks that is only air. Everything from here up is open to the sky
+    pub fn sky_layer(&self) -> i32 {
+        self.params.world_height - 1
+    }
+
+    /// Builds the terrain of a single chunk. Below the bedrock and above the
+    /// generated layers there is only air
+    pub fn generate_chunk(&self, (cx, cy, cz): ChunkCoord) -> Chunk {
+        let perlin2: Add<[f64; 2]> = Add::new(&self.turbulence, &self.noise_1);
+        let chunk_pos = (cx as f64, cy as f64, cz as f64);
+        let height = self.params.world_height;
+        if cy == 0 {
+            make_bottom_layer(
+                chunk_pos,
+                &self.perlin3,
+                &self.params,
+                &self.bottom_bands,
+                self.terrain,
+            )
+        } else if !(0..height - 1).contains(&cy) {
+            make_air_layer()
+        } else if cy >= (height - 2) {
+            make_top_layer(
+                chunk_pos,
+                &perlin2,
+                &self.perlin3,
+                &self.params,
+                self.terrain,
+            )
+        } else {
+            make_mid_layer(chunk_pos, &self.perlin3, &self.params, &self.mid_bands)
         }
     }
-    return chunks;
+}
+
+/// Creates an empty world. Its chunks are generated from the seed and params as they get loaded
+pub fn make_world(seed: u32, params: WorldGenParams, blocks: Arc<BlockRegistry>) -> World {
+    World::new(WorldGen::new(seed, params, blocks))
+}
+
+pub fn random_seed() -> u32 {
+    use rand::Rng;
+    rand::thread_rng().gen()
 }
 
 fn make_top_layer(
     (cx, cy, cz): (f64, f64, f64),
     noise: &Add<[f64; 2]>,
     noise_3_d: &Perlin,
+    params: &WorldGenParams,
+    terrain: TerrainBlocks,
 ) -> Chunk {
     // Array that we'll copy into chunks
     let mut data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE] =
@@ -71,29 +211,32 @@ fn make_top_layer(
 
                 // Get the height value from noise
                 // Noise originally ranges from -2.0 to 2.0, so we adjust it to be between 0 and 1
-                let noise_val =
-                    (noise.get([world_x * PERLIN_STEP_2D, world_z * PERLIN_STEP_2D]) + 2.0) / 4.0;
+                let step_2d = params.perlin_step_2d;
+                let noise_val = (noise.get([world_x * step_2d, world_z * step_2d]) + 2.0) / 4.0;
                 let height = (noise_val * CHUNK_SIZE as f64).floor()
-                    + ((WORLD_DIMS.1 - 2) * CHUNK_SIZE) as f64;
+                    + ((params.world_height - 2) * CHUNK_SIZE as i32) as f64;
 
                 // If our coordinate is taller than the height, we place air
                 // Otherwise we place a dirt block
                 if world_y > height {
-                    data[x][y][z] = 0;
+                    data[x][y][z] = AIR;
                 } else {
                     if world_y == height {
-                        data[x][y][z] = 1;
+                        data[x][y][z] = terrain.grass;
                     } else {
-                        data[x][y][z] = 2;
-                        if world_y <= height - 3.0 {
-                            let noise_val_3_d = (noise_3_d.get([
-                                world_x * PERLIN_STEP_TOP,
-                                world_y * PERLIN_STEP_TOP,
-                                world_z * PERLIN_STEP_TOP,
-                            ]) + 1.0)
-                                / 2.0;
-                            let material = (noise_val_3_d + 2.0).round() as u8;
-                            data[x][y][z] = material;
+                        data[x][y][z] = terrain.dirt;
+                        if world_y <= height - params.dirt_depth {
+                            let step = params.perlin_step_top;
+                            let noise_val_3_d =
+                                (noise_3_d.get([world_x * step, world_y * step, world_z * step])
+                                    + 1.0)
+                                    / 2.0;
+                            // Patches of dirt in the rock
+                            data[x][y][z] = if noise_val_3_d < 0.5 {
+                                terrain.dirt
+                            } else {
+                                terrain.rock
+                            };
                         }
                     }
                 }
@@ -117,7 +260,12 @@ fn make_air_layer() -> Chunk {
     Chunk { data }
 }
 
-fn make_mid_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk {
+fn make_mid_layer(
+    (cx, cy, cz): (f64, f64, f64),
+    noise_3_d: &Perlin,
+    params: &WorldGenParams,
+    bands: &[(f64, u8)],
+) -> Chunk {
     // Array that we'll copy into chunks
     let mut data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE] =
         [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
@@ -132,13 +280,10 @@ fn make_mid_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk {
 
                 // Get noise
                 // Noise adjusted to range from 0 - 1
-                let noise_val = (noise_3_d.get([
-                    world_x * PERLIN_STEP_MID,
-                    world_y * PERLIN_STEP_MID,
-                    world_z * PERLIN_STEP_MID,
-                ]) + 1.0)
-                    / 2.0;
-                let material = mid_material(noise_val);
+                let step = params.perlin_step_mid;
+                let noise_val =
+                    (noise_3_d.get([world_x * step, world_y * step, world_z * step]) + 1.0) / 2.0;
+                let material = band_material(bands, noise_val);
                 data[x][y][z] = material;
             }
         }
@@ -146,7 +291,13 @@ fn make_mid_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk {
     Chunk { data }
 }
 
-fn make_bottom_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk {
+fn make_bottom_layer(
+    (cx, cy, cz): (f64, f64, f64),
+    noise_3_d: &Perlin,
+    params: &WorldGenParams,
+    bands: &[(f64, u8)],
+    terrain: TerrainBlocks,
+) -> Chunk {
     // Array that we'll copy into chunks
     let mut data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE] =
         [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
@@ -162,15 +313,13 @@ fn make_bottom_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk
                 // Get noise
                 // Noise adjusted to range from 0 - 1
                 if y == 0 {
-                    data[x][y][z] = 7;
+                    data[x][y][z] = terrain.bedrock;
                 } else {
-                    let noise_val = (noise_3_d.get([
-                        world_x * PERLIN_STEP_MID,
-                        world_y * PERLIN_STEP_MID,
-                        world_z * PERLIN_STEP_MID,
-                    ]) + 1.0)
-                        / 2.0;
-                    let material = bottom_material(noise_val);
+                    let step = params.perlin_step_mid;
+                    let noise_val =
+                        (noise_3_d.get([world_x * step, world_y * step, world_z * step]) + 1.0)
+                            / 2.0;
+                    let material = band_material(bands, noise_val);
                     data[x][y][z] = material;
                 }
             }
@@ -179,47 +328,12 @@ fn make_bottom_layer((cx, cy, cz): (f64, f64, f64), noise_3_d: &Perlin) -> Chunk
     Chunk { data }
 }
 
-// ROCK -> 3
-// IRON -> 4
-// GOLD -> 5
-// DIAMOND -> 6
-// BEDROCK -> 7
-fn mid_material(noise: f64) -> u8 {
-    if noise <= 0.3 {
-        3
-    } else if noise <= 0.33 {
-        4
-    } else if noise <= 0.44 {
-        3
-    } else if noise <= 0.55 {
-        0
-    } else if noise <= 0.7 {
-        3
-    } else if noise <= 0.73 {
-        5
-    } else {
-        3
-    }
-}
-
-fn bottom_material(noise: f64) -> u8 {
-    if noise <= 0.01 {
-        7
-    } else if noise <= 0.3 {
-        3
-    } else if noise <= 0.32 {
-        4
-    } else if noise <= 0.44 {
-        3
-    } else if noise <= 0.55 {
-        0
-    } else if noise <= 0.7 {
-        3
-    } else if noise <= 0.72 {
-        5
-    } else if noise <= 0.98 {
-        3
-    } else {
-        7
-    }
+/// Picks the first band that the noise falls under. Anything above every band gets the last band's material
+fn band_material(bands: &[(f64, u8)], noise: f64) -> u8 {
+    bands
+        .iter()
+        .find(|(max_noise, _)| noise <= *max_noise)
+        .or_else(|| bands.last())
+        .map(|(_, material)| *material)
+        .unwrap_or(AIR)
 }
@@ -0,0 +1,101 @@
+mod common;
+
+use engine3d::blocks::{BlockRegistry, RenderMode, AIR};
+use engine3d::mesher::Face;
+
+const AIR_JSON: &str = r#"{ "id": 0, "name": "air", "solid": false, "transparent": true }"#;
+
+fn registry(blocks: &[&str]) -> anyhow::Result<BlockRegistry> {
+    BlockRegistry::from_json(&format!("[{}]", blocks.join(",")))
+}
+
+#[test]
+fn content_blocks_load() {
+    let blocks = common::blocks();
+    assert_eq!(blocks.get(AIR).name, "air");
+    let bedrock = blocks.get(blocks.id("bedrock").unwrap());
+    assert!(!bedrock.breakable && bedrock.solid);
+    for block in blocks.iter().skip(1) {
+        for face in Face::ALL.iter() {
+            assert!(blocks.face_texture(block.id, *face).is_some());
+        }
+    }
+}
+
+#[test]
+fn faces_fall_back_on_all() {
+    let blocks = registry(&[
+        AIR_JSON,
+        r#"{ "id": 1, "name": "grass", "textures": { "all": "dirt.png", "top": "grass.png" } }"#,
+        r#"{ "id": 2, "name": "dirt", "textures": { "all": "dirt.png" } }"#,
+    ])
+    .unwrap();
+    // Each texture is only listed once
+    assert_eq!(blocks.textures().len(), 2);
+    let top = blocks.face_texture(1, Face::PosY).unwrap();
+    let side = blocks.face_texture(1, Face::NegX).unwrap();
+    assert_eq!(blocks.textures()[top], "grass.png");
+    assert_eq!(blocks.textures()[side], "dirt.png");
+    assert_eq!(blocks.face_texture(2, Face::PosY), Some(side));
+    assert_eq!(blocks.face_texture(AIR, Face::PosY), None);
+}
+
+#[test]
+fn defaults_make_a_plain_solid_block() {
+    let blocks = registry(&[
+        AIR_JSON,
+        r#"{ "id": 1, "name": "rock", "textures": { "all": "rock.png" } }"#,
+    ])
+    .unwrap();
+    let rock = blocks.get(1);
+    assert!(rock.solid && rock.breakable && !rock.transparent);
+    assert_eq!(rock.render, RenderMode::Opaque);
+    assert_eq!(rock.emission, 0);
+    assert_eq!(rock.break_sound, None);
+}
+
+#[test]
+fn unknown_ids_act_like_air() {
+    let blocks = registry(&[AIR_JSON]).unwrap();
+    assert_eq!(blocks.get(200).id, AIR);
+    assert_eq!(blocks.face_texture(200, Face::PosX), None);
+    assert_eq!(blocks.id("rock"), None);
+}
+
+#[test]
+fn bad_registries_are_rejected() {
+    let rock = r#"{ "id": 1, "name": "rock", "textures": { "all": "rock.png" } }"#;
+    // Missing air
+    assert!(registry(&[rock]).is_err());
+    // Gap in the ids
+    assert!(registry(&[
+        AIR_JSON,
+        r#"{ "id": 2, "name": "rock", "textures": { "all": "rock.png" } }"#
+    ])
+    .is_err());
+    // Two blocks with the same name
+    assert!(registry(&[
+        AIR_JSON,
+        rock,
+        r#"{ "id": 2, "name": "rock", "textures": { "all": "rock.png" } }"#
+    ])
+    .is_err());
+    // A face without a texture
+    assert!(registry(&[
+        AIR_JSON,
+        r#"{ "id": 1, "name": "rock", "textures": { "top": "rock.png" } }"#
+    ])
+    .is_err());
+    // Drawn see-through without letting anything be seen through it
+    assert!(registry(&[
+        AIR_JSON,
+        r#"{ "id": 1, "name": "glass", "textures": { "all": "rock.png" }, "render": "translucent" }"#
+    ])
+    .is_err());
+    // Too bright
+    assert!(registry(&[
+        AIR_JSON,
+        r#"{ "id": 1, "name": "lamp", "textures": { "all": "gold.png" }, "emission": 16 }"#
+    ])
+    .is_err());
+}
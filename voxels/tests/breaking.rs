@@ -0,0 +1,129 @@
+mod common;
+
+use common::blocks;
+use engine3d::blocks::BlockType;
+use engine3d::breaking::{Breaking, Tool, CRACK_STAGES, SECONDS_PER_HARDNESS};
+
+const DT: f32 = 0.05;
+
+fn block(id: u8, hardness: f32) -> BlockType {
+    serde_json::from_str(&format!(
+        r#"{{ "id": {}, "name": "block {}", "hardness": {}, "material": "stone" }}"#,
+        id, id, hardness
+    ))
+    .unwrap()
+}
+
+fn pickaxe() -> Tool {
+    serde_json::from_str(r#"{ "name": "pickaxe", "speed": 4.0, "materials": ["stone"] }"#).unwrap()
+}
+
+// Holds the mouse down on a block until it breaks, returning how many steps that took
+fn steps_to_break(breaking: &mut Breaking, block: &BlockType, tool: &Tool) -> usize {
+    (1..10_000)
+        .find(|_| breaking.update(Some(([0, 0, 0], block)), tool, DT))
+        .unwrap()
+}
+
+#[test]
+fn harder_blocks_take_longer() {
+    let mut breaking = Breaking::default();
+    let soft = steps_to_break(&mut breaking, &block(1, 0.5), &Tool::hand());
+    let hard = steps_to_break(&mut breaking, &block(2, 2.0), &Tool::hand());
+    // Give or take a step for rounding
+    let expected = |hardness: f32| (hardness * SECONDS_PER_HARDNESS / DT) as usize;
+    assert!(soft.max(expected(0.5)) - soft.min(expected(0.5)) <= 1);
+    assert!(hard.max(expected(2.0)) - hard.min(expected(2.0)) <= 1);
+    // Breaking starts over for the next block
+    assert_eq!(breaking, Breaking::default());
+}
+
+#[test]
+fn tools_speed_breaking_up() {
+    let rock = block(1, 2.0);
+    let by_hand = steps_to_break(&mut Breaking::default(), &rock, &Tool::hand());
+    let with_tool = steps_to_break(&mut Breaking::default(), &rock, &pickaxe());
+    assert!(with_tool.max(by_hand / 4) - with_tool.min(by_hand / 4) <= 1);
+}
+
+#[test]
+fn tools_only_help_with_the_materials_they_are_meant_for() {
+    let mut dirt = block(1, 2.0);
+    dirt.material = Some("soil".to_string());
+    let by_hand = steps_to_break(&mut Breaking::default(), &dirt, &Tool::hand());
+    assert_eq!(
+        steps_to_break(&mut Breaking::default(), &dirt, &pickaxe()),
+        by_hand
+    );
+    // Nor with blocks that aren't made of anything in particular
+    dirt.material = None;
+    assert_eq!(
+        steps_to_break(&mut Breaking::default(), &dirt, &pickaxe()),
+        by_hand
+    );
+
+    // The blocks in content/blocks.json
+    let blocks = blocks();
+    let get = |name| blocks.get(blocks.id(name).unwrap());
+    let pickaxe = pickaxe();
+    assert_eq!(pickaxe.speed_on(get("rock")), 4.0);
+    assert_eq!(pickaxe.speed_on(get("diamond")), 4.0);
+    assert_eq!(pickaxe.speed_on(get("dirt")), 1.0);
+    assert_eq!(Tool::hand().speed_on(get("rock")), 1.0);
+}
+
+#[test]
+fn blocks_with_no_hardness_break_right_away() {
+    let mut breaking = Breaking::default();
+    assert!(breaking.update(Some(([0, 0, 0], &block(1, 0.0))), &Tool::hand(), DT));
+}
+
+#[test]
+fn unbreakable_blocks_never_break() {
+    let mut bedrock = block(1, 0.0);
+    bedrock.breakable = false;
+    let mut breaking = Breaking::default();
+    for _ in 0..100 {
+        assert!(!breaking.update(Some(([0, 0, 0], &bedrock)), &Tool::hand(), DT));
+    }
+    assert_eq!(breaking.stage(), None);
+}
+
+#[test]
+fn changing_target_or_letting_go_starts_over() {
+    let rock = block(1, 1.0);
+    let mut breaking = Breaking::default();
+    for _ in 0..10 {
+        breaking.update(Some(([0, 0, 0], &rock)), &Tool::hand(), DT);
+    }
+    assert!(breaking.progress > 0.4);
+
+    // Looking at the next voxel over
+    breaking.update(Some(([1, 0, 0], &rock)), &Tool::hand(), DT);
+    assert_eq!(breaking.target, Some(([1, 0, 0], 1)));
+    assert!((breaking.progress - DT).abs() < 1e-6);
+
+    // The block in the voxel changed
+    breaking.update(Some(([1, 0, 0], &block(2, 1.0))), &Tool::hand(), DT);
+    assert!((breaking.progress - DT).abs() < 1e-6);
+
+    // Letting go of the mouse
+    breaking.update(None, &Tool::hand(), DT);
+    assert_eq!(breaking, Breaking::default());
+}
+
+#[test]
+fn cracks_grow_in_stages() {
+    let rock = block(1, 1.0);
+    let mut breaking = Breaking::default();
+    assert_eq!(breaking.stage(), None);
+    let mut stages = vec![];
+    while !breaking.update(Some(([0, 0, 0], &rock)), &Tool::hand(), 0.01) {
+        stages.push(breaking.stage().unwrap());
+    }
+    assert_eq!(stages[0], 0);
+    assert_eq!(*stages.last().unwrap(), CRACK_STAGES - 1);
+    assert!(stages
+        .windows(2)
+        .all(|pair| pair[1] == pair[0] || pair[1] == pair[0] + 1));
+}
@@ -0,0 +1,67 @@
+use cgmath::Point3;
+use engine3d::camera::Camera;
+
+// At the origin looking down -z
+fn camera() -> Camera {
+    Camera {
+        eye: (0.0, 0.0, 0.0).into(),
+        target: (0.0, 0.0, -1.0).into(),
+        up: cgmath::Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 90.0,
+        znear: 0.1,
+        zfar: 100.0,
+    }
+}
+
+// A cube with sides of 2 around a point
+fn sees(camera: &Camera, x: f32, y: f32, z: f32) -> bool {
+    camera.frustum().intersects_aabb(
+        Point3::new(x - 1.0, y - 1.0, z - 1.0),
+        Point3::new(x + 1.0, y + 1.0, z + 1.0),
+    )
+}
+
+#[test]
+fn boxes_in_front_are_seen() {
+    let camera = camera();
+    assert!(sees(&camera, 0.0, 0.0, -10.0));
+    // Just inside of the 90 degree field of view
+    assert!(sees(&camera, 9.0, 0.0, -10.0));
+    assert!(sees(&camera, 0.0, -9.0, -10.0));
+}
+
+#[test]
+fn boxes_out_of_view_are_culled() {
+    let camera = camera();
+    // Behind
+    assert!(!sees(&camera, 0.0, 0.0, 10.0));
+    // Off to the sides
+    assert!(!sees(&camera, 15.0, 0.0, -10.0));
+    assert!(!sees(&camera, 0.0, 15.0, -10.0));
+    // Past the far plane
+    assert!(!sees(&camera, 0.0, 0.0, -150.0));
+}
+
+#[test]
+fn boxes_on_the_edge_are_seen() {
+    let camera = camera();
+    // The center is outside of the field of view, but a corner pokes into it
+    assert!(sees(&camera, 10.5, 0.0, -10.0));
+    // Straddling the far plane
+    assert!(sees(&camera, 0.0, 0.0, -100.5));
+    // Big enough to wrap around the camera
+    assert!(camera.frustum().intersects_aabb(
+        Point3::new(-50.0, -50.0, -50.0),
+        Point3::new(50.0, 50.0, 50.0)
+    ));
+}
+
+#[test]
+fn frustum_follows_the_camera() {
+    let mut camera = camera();
+    camera.eye = (100.0, 0.0, 0.0).into();
+    camera.target = (101.0, 0.0, 0.0).into();
+    assert!(sees(&camera, 110.0, 0.0, 0.0));
+    assert!(!sees(&camera, 0.0, 0.0, -10.0));
+}
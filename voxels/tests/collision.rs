@@ -0,0 +1,138 @@
+mod common;
+
+use cgmath::{Point3, Vector3};
+use common::{world, SIZE};
+use engine3d::geom::BBox;
+use engine3d::world::World;
+
+// Player shaped, a bit over half a voxel wide and almost two voxels tall
+const HALF_X: f32 = SIZE * 0.3;
+const HALF_Y: f32 = SIZE * 0.9;
+
+// A player sized box
+fn hitbox(x: f32, y: f32, z: f32) -> BBox {
+    BBox {
+        center: Point3::new(x, y, z),
+        half_extents: Vector3::new(HALF_X, HALF_Y, HALF_X),
+    }
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+#[test]
+fn lands_on_the_floor() {
+    let world = world(&[[0, -1, 0]]);
+    let sweep = world.sweep(hitbox(2.0, 6.0, 2.0), Vector3::new(0.0, -10.0, 0.0));
+    assert!(close(sweep.hitbox.center.y, HALF_Y));
+    assert!(close(sweep.moved.y, HALF_Y - 6.0));
+    assert_eq!(sweep.normal, [0, 1, 0]);
+    assert!(sweep.on_ground());
+    assert!(!sweep.hit_ceiling());
+}
+
+#[test]
+fn bumps_into_the_ceiling() {
+    let world = world(&[[0, 2, 0]]);
+    let sweep = world.sweep(hitbox(2.0, 2.0, 2.0), Vector3::new(0.0, 10.0, 0.0));
+    assert!(close(sweep.hitbox.center.y, 2.0 * SIZE - HALF_Y));
+    assert_eq!(sweep.normal, [0, -1, 0]);
+    assert!(sweep.hit_ceiling());
+    assert!(!sweep.on_ground());
+}
+
+#[test]
+fn lands_on_the_edge_of_a_block() {
+    // The middle of the box is over empty space, but its side hangs over the rock
+    let world = world(&[[1, -1, 0]]);
+    let sweep = world.sweep(hitbox(SIZE, 6.0, 2.0), Vector3::new(0.0, -10.0, 0.0));
+    assert!(close(sweep.hitbox.center.y, HALF_Y));
+    assert!(sweep.on_ground());
+}
+
+#[test]
+fn lands_on_the_corner_of_a_block() {
+    // Only one corner of the box is over the rock
+    let world = world(&[[1, -1, 1]]);
+    let sweep = world.sweep(hitbox(SIZE, 6.0, SIZE), Vector3::new(0.0, -10.0, 0.0));
+    assert!(close(sweep.hitbox.center.y, HALF_Y));
+    assert!(sweep.on_ground());
+
+    // Moving sideways into the corner of a wall is stopped too
+    let world = self::world(&[[1, 1, 1]]);
+    let sweep = world.sweep(hitbox(2.0, SIZE, SIZE), Vector3::new(5.0, 0.0, 0.0));
+    assert!(close(sweep.hitbox.center.x, SIZE - HALF_X));
+    assert_eq!(sweep.normal, [-1, 0, 0]);
+}
+
+#[test]
+fn slides_along_walls() {
+    let world = world(&[[1, 0, 0]]);
+    let sweep = world.sweep(hitbox(2.0, 2.0, 2.0), Vector3::new(5.0, 0.0, 3.0));
+    assert!(close(sweep.hitbox.center.x, SIZE - HALF_X));
+    assert!(close(sweep.hitbox.center.z, 5.0));
+    assert_eq!(sweep.normal, [-1, 0, 0]);
+}
+
+#[test]
+fn fast_boxes_dont_tunnel_through_blocks() {
+    let world = world(&[[5, 0, 0]]);
+    let sweep = world.sweep(hitbox(2.0, 2.0, 2.0), Vector3::new(100.0, 0.0, 0.0));
+    assert!(close(sweep.hitbox.center.x, 5.0 * SIZE - HALF_X));
+    let sweep = world.sweep(hitbox(2.0, 2.0, 2.0), Vector3::new(-30.0, 0.0, 0.0));
+    assert!(close(sweep.moved.x, -30.0));
+    assert_eq!(sweep.normal, [0, 0, 0]);
+}
+
+#[test]
+fn walks_along_the_floor_it_rests_on() {
+    let world = world(&[[0, -1, 0], [1, -1, 0], [2, -1, 0], [3, -1, 0]]);
+    let sweep = world.sweep(
+        hitbox(2.0, HALF_Y, 2.0),
+        Vector3::new(2.0 * SIZE, -0.1, 0.0),
+    );
+    assert!(close(sweep.hitbox.center.x, 2.0 + 2.0 * SIZE));
+    assert!(close(sweep.hitbox.center.y, HALF_Y));
+    assert!(sweep.on_ground());
+}
+
+#[test]
+fn boxes_inside_a_block_can_move_out() {
+    let world = world(&[[0, 0, 0]]);
+    let sweep = world.sweep(hitbox(2.0, 2.0, 2.0), Vector3::new(0.0, 3.0, 0.0));
+    assert!(close(sweep.hitbox.center.y, 5.0));
+    assert_eq!(sweep.normal, [0, 0, 0]);
+}
+
+#[test]
+fn tall_boxes_hit_blocks_at_head_height() {
+    // The rock is a voxel off the ground, so it misses the player's feet but not their head
+    let world = world(&[[1, 1, 0]]);
+    let sweep = world.sweep(hitbox(2.0, HALF_Y, 2.0), Vector3::new(5.0, 0.0, 0.0));
+    assert!(close(sweep.hitbox.center.x, SIZE - HALF_X));
+    assert_eq!(sweep.normal, [-1, 0, 0]);
+    // Ducking under it works
+    let mut short = hitbox(2.0, HALF_X, 2.0);
+    short.half_extents.y = HALF_X;
+    let sweep = world.sweep(short, Vector3::new(5.0, 0.0, 0.0));
+    assert!(close(sweep.moved.x, 5.0));
+}
+
+#[test]
+fn overlaps_every_voxel_the_box_reaches_into() {
+    // Standing in voxel [0, 0, 0], tall enough to reach into the one above
+    let player = hitbox(2.0, HALF_Y, 2.0);
+    assert!(World::overlaps_voxel(&player, [0, 0, 0]));
+    assert!(World::overlaps_voxel(&player, [0, 1, 0]));
+    assert!(!World::overlaps_voxel(&player, [0, 2, 0]));
+    // Resting on the floor or against a wall is only touching
+    assert!(!World::overlaps_voxel(&player, [0, -1, 0]));
+    let against_wall = hitbox(SIZE - HALF_X, HALF_Y, 2.0);
+    assert!(!World::overlaps_voxel(&against_wall, [1, 0, 0]));
+    // Straddling the boundary reaches into both sides, even away from the eyes
+    let straddling = hitbox(SIZE, HALF_Y, 2.0);
+    assert!(World::overlaps_voxel(&straddling, [0, 0, 0]));
+    assert!(World::overlaps_voxel(&straddling, [1, 0, 0]));
+    assert!(World::overlaps_voxel(&straddling, [1, 1, 0]));
+}
@@ -0,0 +1,65 @@
+// Fixtures shared by the integration tests. Each test file only uses some of them
+#![allow(dead_code)]
+
+use engine3d::blocks::BlockRegistry;
+use engine3d::voxel::{Chunk, CHUNK_SIZE, VOXEL_HALFWIDTH};
+use engine3d::world::World;
+use engine3d::world_gen::{WorldGen, WorldGenParams};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const N: usize = CHUNK_SIZE;
+pub const ROCK: u8 = 3;
+pub const GLOWSTONE: u8 = 10; // Gives off light
+pub const SIZE: f32 = VOXEL_HALFWIDTH * 2.0; // Width of a voxel in world units
+
+/// The blocks in content/blocks.json
+pub fn blocks() -> Arc<BlockRegistry> {
+    Arc::new(
+        BlockRegistry::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../content/blocks.json"
+        ))
+        .unwrap(),
+    )
+}
+
+/// A world with nothing loaded yet, generated from seed 0
+pub fn empty_world() -> World {
+    seeded_world(0)
+}
+
+/// A world with nothing loaded yet, generated from the given seed
+pub fn seeded_world(seed: u32) -> World {
+    World::new(WorldGen::new(seed, WorldGenParams::default(), blocks()))
+}
+
+pub fn empty_chunk() -> Chunk {
+    Chunk {
+        data: [[[0; N]; N]; N],
+    }
+}
+
+/// Empty chunks around the origin, with rock in the given voxels
+pub fn world(rocks: &[[i32; 3]]) -> World {
+    let mut world = empty_world();
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                world.insert((x, y, z), empty_chunk());
+            }
+        }
+    }
+    for rock in rocks {
+        world.set_voxel(World::voxel_center(*rock), ROCK);
+    }
+    world
+}
+
+/// An empty directory to save into, unique to the test (and the test run) that asks for it
+pub fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("engine3d-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
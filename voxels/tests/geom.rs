@@ -0,0 +1,254 @@
+use cgmath::{Point3, Vector3};
+use engine3d::geom::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Every property is checked against this many random shapes
+const CASES: usize = 2000;
+const EPSILON: f32 = 1e-3;
+
+fn point(rng: &mut StdRng) -> Pos3 {
+    Point3::new(
+        rng.gen_range(-10.0..10.0),
+        rng.gen_range(-10.0..10.0),
+        rng.gen_range(-10.0..10.0),
+    )
+}
+
+fn bbox(rng: &mut StdRng) -> BBox {
+    BBox {
+        center: point(rng),
+        half_extents: Vector3::new(
+            rng.gen_range(0.1..5.0),
+            rng.gen_range(0.1..5.0),
+            rng.gen_range(0.1..5.0),
+        ),
+    }
+}
+
+fn sphere(rng: &mut StdRng) -> Sphere {
+    Sphere {
+        c: point(rng),
+        r: rng.gen_range(0.1..5.0),
+    }
+}
+
+fn plane(rng: &mut StdRng) -> Plane {
+    Plane {
+        n: (point(rng) - Point3::new(0.0, 0.0, 0.0)).normalize(),
+        d: rng.gen_range(-10.0..10.0),
+    }
+}
+
+fn moved_box(b: &BBox, by: Vec3) -> BBox {
+    BBox {
+        center: b.center + by,
+        ..*b
+    }
+}
+
+fn moved_sphere(s: &Sphere, by: Vec3) -> Sphere {
+    Sphere { c: s.c + by, ..*s }
+}
+
+// A random point inside of the box (or on its surface)
+fn point_in_box(rng: &mut StdRng, b: &BBox) -> Pos3 {
+    let mut p = b.center;
+    for axis in 0..3 {
+        p[axis] += rng.gen_range(-1.0..=1.0) * b.half_extents[axis];
+    }
+    p
+}
+
+// All 8 corners' distances in front of the plane, nearest first
+fn corner_distances(b: &BBox, p: &Plane) -> Vec<f32> {
+    let mut distances: Vec<f32> = (0..8)
+        .map(|i| {
+            let mut corner = b.center;
+            for axis in 0..3 {
+                let side = if i & (1 << axis) == 0 { -1.0 } else { 1.0 };
+                corner[axis] += side * b.half_extents[axis];
+            }
+            corner.dot(p.n) - p.d
+        })
+        .collect();
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    distances
+}
+
+// How far apart two boxes are (0 if they overlap), found separately for each axis
+fn box_gap(b1: &BBox, b2: &BBox) -> f32 {
+    (0..3)
+        .map(|axis| {
+            ((b1.center[axis] - b2.center[axis]).abs()
+                - b1.half_extents[axis]
+                - b2.half_extents[axis])
+                .max(0.0)
+                .powi(2)
+        })
+        .sum::<f32>()
+        .sqrt()
+}
+
+fn cases(seed: u64, mut check: impl FnMut(&mut StdRng)) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..CASES {
+        check(&mut rng);
+    }
+}
+
+#[test]
+fn closest_point_is_in_the_box_and_nothing_in_it_is_closer() {
+    cases(1, |rng| {
+        let b = bbox(rng);
+        let p = point(rng);
+        let closest = closest_point_box(&b, p);
+        for axis in 0..3 {
+            assert!((closest[axis] - b.center[axis]).abs() <= b.half_extents[axis] + EPSILON);
+        }
+        let other = point_in_box(rng, &b);
+        assert!(closest.distance(p) <= other.distance(p) + EPSILON);
+        // Points already inside are their own closest point
+        assert_eq!(closest_point_box(&b, other), other);
+    });
+}
+
+#[test]
+fn box_box_touching_matches_the_gap_between_them() {
+    cases(2, |rng| {
+        let (b1, b2) = (bbox(rng), bbox(rng));
+        let touching = touching_box_box(&b1, &b2);
+        assert_eq!(touching, touching_box_box(&b2, &b1));
+        assert_eq!(touching, box_gap(&b1, &b2) == 0.0);
+    });
+}
+
+#[test]
+fn box_box_disp_pushes_them_apart() {
+    cases(3, |rng| {
+        let (b1, b2) = (bbox(rng), bbox(rng));
+        match disp_box_box(&b1, &b2) {
+            Some(disp) => {
+                assert!(touching_box_box(&b1, &b2));
+                // Each box moves half of the way, so together they end up just touching
+                let b1 = moved_box(&b1, disp);
+                let b2 = moved_box(&b2, -disp);
+                for axis in 0..3 {
+                    let apart = (b1.center[axis] - b2.center[axis]).abs();
+                    let reach = b1.half_extents[axis] + b2.half_extents[axis];
+                    assert!((apart - reach).abs() < EPSILON, "{} vs {}", apart, reach);
+                }
+            }
+            None => assert!(!touching_box_box(&b1, &b2)),
+        }
+    });
+}
+
+#[test]
+fn box_sphere_touching_matches_the_closest_point() {
+    cases(4, |rng| {
+        let (b, s) = (bbox(rng), sphere(rng));
+        let distance = closest_point_box(&b, s.c).distance(s.c);
+        assert_eq!(touching_box_sphere(&b, &s), distance <= s.r);
+        // A sphere is touched by any point of the box that's inside of it
+        let p = point_in_box(rng, &b);
+        if p.distance(s.c) < s.r {
+            assert!(touching_box_sphere(&b, &s));
+        }
+        // A sphere centered inside of the box always touches it
+        let inside = Sphere { c: p, ..s };
+        assert!(touching_box_sphere(&b, &inside));
+    });
+}
+
+#[test]
+fn box_sphere_disp_pushes_the_sphere_out() {
+    cases(5, |rng| {
+        let b = bbox(rng);
+        // Half of the spheres start with their centers inside of the box
+        let mut s = sphere(rng);
+        if rng.gen() {
+            s.c = point_in_box(rng, &b);
+        }
+        match disp_box_sphere(&b, &s) {
+            Some(disp) => {
+                assert!(touching_box_sphere(&b, &s));
+                // Pushed out just far enough to be touching
+                let pushed = moved_sphere(&s, disp);
+                let distance = closest_point_box(&b, pushed.c).distance(pushed.c);
+                assert!((distance - s.r).abs() < EPSILON, "{} vs {}", distance, s.r);
+                // And no farther than it would take to push the box's center out
+                assert!(disp.magnitude() <= b.half_extents.magnitude() + s.r + EPSILON);
+            }
+            None => {
+                let distance = closest_point_box(&b, s.c).distance(s.c);
+                assert!(distance >= s.r - EPSILON);
+            }
+        }
+    });
+}
+
+#[test]
+fn sphere_sphere_touching_and_disp_agree() {
+    cases(6, |rng| {
+        let (s1, s2) = (sphere(rng), sphere(rng));
+        let touching = touching_sphere_sphere(&s1, &s2);
+        assert_eq!(touching, touching_sphere_sphere(&s2, &s1));
+        assert_eq!(touching, s1.c.distance(s2.c) <= s1.r + s2.r);
+        match disp_sphere_sphere(&s1, &s2) {
+            Some(disp) => {
+                assert!(touching);
+                let pushed = moved_sphere(&s2, disp);
+                assert!((s1.c.distance(pushed.c) - (s1.r + s2.r)).abs() < EPSILON);
+            }
+            None => assert!(s1.c.distance(s2.c) >= s1.r + s2.r - EPSILON),
+        }
+    });
+}
+
+#[test]
+fn sphere_plane_touching_and_disp_agree() {
+    cases(7, |rng| {
+        let (s, p) = (sphere(rng), plane(rng));
+        let dist = s.c.dot(p.n) - p.d;
+        assert_eq!(touching_sphere_plane(&s, &p), dist.abs() <= s.r);
+        match disp_sphere_plane(&s, &p) {
+            Some(disp) => {
+                // Ends up resting on the front of the plane
+                let pushed = moved_sphere(&s, disp);
+                assert!((pushed.c.dot(p.n) - p.d - s.r).abs() < EPSILON);
+            }
+            None => assert!(dist.abs() >= s.r - EPSILON),
+        }
+    });
+}
+
+#[test]
+fn box_plane_touching_matches_the_corners() {
+    cases(8, |rng| {
+        let (b, p) = (bbox(rng), plane(rng));
+        // Touching if the corners aren't all on the same side of the plane
+        let corners = corner_distances(&b, &p);
+        let straddles = corners[0] <= EPSILON && corners[7] >= -EPSILON;
+        let clear = corners[0] > EPSILON || corners[7] < -EPSILON;
+        if straddles && !clear {
+            assert!(touching_box_plane(&b, &p));
+        }
+        if clear {
+            assert!(!touching_box_plane(&b, &p));
+        }
+        assert_eq!(touching_box_plane(&b, &p), disp_box_plane(&b, &p).is_some());
+    });
+}
+
+#[test]
+fn box_plane_disp_pushes_the_box_in_front() {
+    cases(9, |rng| {
+        let (b, p) = (bbox(rng), plane(rng));
+        if let Some(disp) = disp_box_plane(&b, &p) {
+            let pushed = moved_box(&b, disp);
+            let corners = corner_distances(&pushed, &p);
+            assert!(corners[0] >= -EPSILON, "{:?}", corners);
+        }
+    });
+}
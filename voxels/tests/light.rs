@@ -0,0 +1,262 @@
+mod common;
+
+use common::{empty_chunk as empty, empty_world as world, GLOWSTONE, N, ROCK};
+use engine3d::light::{light_alone, MAX_LIGHT};
+use engine3d::voxel::{Chunk, VOXEL_HALFWIDTH};
+use engine3d::world::{ChunkCoord, World};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// With the default params, chunk layers from 4 up are open to the sky
+const SKY: i32 = 4;
+const UNDERGROUND: i32 = 1;
+
+fn full(voxel: u8) -> Chunk {
+    Chunk {
+        data: [[[voxel; N]; N]; N],
+    }
+}
+
+// Center of a voxel, counting in voxels from the world origin
+fn at(x: i32, y: i32, z: i32) -> cgmath::Point3<f32> {
+    let size = VOXEL_HALFWIDTH * 2.0;
+    cgmath::Point3::new(
+        (x as f32 + 0.5) * size,
+        (y as f32 + 0.5) * size,
+        (z as f32 + 0.5) * size,
+    )
+}
+
+// Lights a chunk the way the game does while streaming chunks in: on its own on a worker first,
+// and then joined up with the chunks around it
+fn insert_lit(world: &mut World, coord: ChunkCoord, chunk: Chunk) {
+    let light = light_alone(&chunk, world.blocks(), coord, world.generator().sky_layer());
+    world.insert_lit(coord, chunk, light);
+}
+
+fn same_light(world: &World, expected: &World, coords: &[ChunkCoord]) {
+    for coord in coords {
+        assert_eq!(
+            world.light(*coord).unwrap().data,
+            expected.light(*coord).unwrap().data,
+            "{:?}",
+            coord
+        );
+    }
+}
+
+fn sky(world: &World, x: i32, y: i32, z: i32) -> u8 {
+    world.light_at(at(x, y, z)).unwrap().0
+}
+
+fn block(world: &World, x: i32, y: i32, z: i32) -> u8 {
+    world.light_at(at(x, y, z)).unwrap().1
+}
+
+#[test]
+fn open_air_is_lit_by_the_sky() {
+    let mut world = world();
+    world.insert((0, SKY, 0), empty());
+    let light = world.light((0, SKY, 0)).unwrap();
+    assert!(light
+        .data
+        .iter()
+        .flatten()
+        .flatten()
+        .all(|l| *l >> 4 == MAX_LIGHT));
+}
+
+#[test]
+fn sealed_caves_are_dark() {
+    let mut world = world();
+    world.insert((0, UNDERGROUND, 0), empty());
+    let light = world.light((0, UNDERGROUND, 0)).unwrap();
+    assert!(light.data.iter().flatten().flatten().all(|l| *l == 0));
+}
+
+#[test]
+fn sky_light_falls_down_shafts_and_spreads_out_at_the_bottom() {
+    // A rock chunk under the sky with a shaft down to a hollow at the bottom
+    let mut chunk = full(ROCK);
+    for y in 1..N {
+        chunk.data[8][y][8] = 0;
+    }
+    for x in 0..N {
+        chunk.data[x][0][8] = 0;
+    }
+    let mut world = world();
+    let base = (SKY - 1) * N as i32;
+    world.insert((0, SKY - 1, 0), chunk);
+
+    assert_eq!(sky(&world, 8, base + N as i32 - 1, 8), MAX_LIGHT);
+    assert_eq!(sky(&world, 8, base + 1, 8), MAX_LIGHT);
+    assert_eq!(sky(&world, 8, base, 8), MAX_LIGHT);
+    assert_eq!(sky(&world, 5, base, 8), MAX_LIGHT - 3);
+    assert_eq!(sky(&world, 8, base + 1, 9), 0);
+}
+
+#[test]
+fn block_light_fades_with_distance() {
+    let mut world = world();
+    world.insert((0, UNDERGROUND, 0), empty());
+    let y = UNDERGROUND * N as i32 + 8;
+    world.set_voxel(at(8, y, 8), GLOWSTONE);
+    let glow = block(&world, 8, y, 8);
+    assert!(glow > 3);
+    assert_eq!(block(&world, 9, y, 8), glow - 1);
+    assert_eq!(block(&world, 8, y - 2, 9), glow - 3);
+    assert_eq!(sky(&world, 9, y, 8), 0);
+}
+
+#[test]
+fn removing_a_light_source_takes_its_light_away() {
+    let mut world = world();
+    world.insert((0, UNDERGROUND, 0), empty());
+    let y = UNDERGROUND * N as i32 + 8;
+    world.set_voxel(at(8, y, 8), GLOWSTONE);
+    world.set_voxel(at(8, y, 8), 0);
+    let light = world.light((0, UNDERGROUND, 0)).unwrap();
+    assert!(light.data.iter().flatten().flatten().all(|l| *l == 0));
+}
+
+#[test]
+fn walls_block_light() {
+    // Two rooms split by a wall at x = 8
+    let mut chunk = empty();
+    for y in 0..N {
+        for z in 0..N {
+            chunk.data[8][y][z] = ROCK;
+        }
+    }
+    let mut world = world();
+    world.insert((0, UNDERGROUND, 0), chunk);
+    let y = UNDERGROUND * N as i32 + 8;
+    world.set_voxel(at(6, y, 8), GLOWSTONE);
+    assert!(block(&world, 7, y, 8) > 0);
+    assert_eq!(block(&world, 9, y, 8), 0);
+
+    // Knocking a hole in the wall lets the light through
+    world.set_voxel(at(8, y, 8), 0);
+    let glow = block(&world, 6, y, 8);
+    assert_eq!(block(&world, 9, y, 8), glow - 3);
+}
+
+#[test]
+fn covering_a_shaft_darkens_it_and_uncovering_relights_it() {
+    let mut world = world();
+    world.insert((0, SKY, 0), empty());
+    world.insert((0, SKY - 1, 0), empty());
+    let top = SKY * N as i32 + N as i32 - 1;
+    // Put a roof over the whole chunk
+    for x in 0..N as i32 {
+        for z in 0..N as i32 {
+            world.set_voxel(at(x, top, z), ROCK);
+        }
+    }
+    assert_eq!(sky(&world, 8, top - 1, 8), 0);
+    assert_eq!(sky(&world, 8, top - 20, 8), 0);
+
+    world.set_voxel(at(8, top, 8), 0);
+    assert_eq!(sky(&world, 8, top - 20, 8), MAX_LIGHT);
+    assert_eq!(sky(&world, 10, top - 20, 8), MAX_LIGHT - 2);
+}
+
+#[test]
+fn light_crosses_chunk_borders() {
+    let mut world = world();
+    world.insert((0, UNDERGROUND, 0), empty());
+    let y = UNDERGROUND * N as i32 + 8;
+    world.set_voxel(at(N as i32 - 1, y, 8), GLOWSTONE);
+    let glow = block(&world, N as i32 - 1, y, 8);
+
+    // A chunk loaded next to the light picks it up
+    world.take_relit();
+    world.insert((1, UNDERGROUND, 0), empty());
+    assert_eq!(block(&world, N as i32 + 2, y, 8), glow - 3);
+
+    // And changes to the light spill back into the chunk next door
+    world.take_relit();
+    world.set_voxel(at(N as i32 + 1, y + 3, 8), GLOWSTONE);
+    assert_eq!(block(&world, N as i32 - 1, y + 3, 8), glow - 2);
+    assert!(world.take_relit().contains(&(0, UNDERGROUND, 0)));
+}
+
+#[test]
+fn lighting_chunks_on_their_own_first_gives_the_same_light() {
+    // Terrain from the surface up past the sky layer
+    let mut coords = vec![];
+    for x in -1..=1 {
+        for y in SKY - 2..=SKY + 1 {
+            for z in -1..=1 {
+                coords.push((x, y, z));
+            }
+        }
+    }
+    let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+    let generator = world().generator().clone();
+    let chunks: Vec<Chunk> = coords
+        .iter()
+        .map(|coord| {
+            let mut chunk = generator.generate_chunk(*coord);
+            // Some glowstone, a few of them close enough to a border to light the chunk next door
+            for _ in 0..3 {
+                let x = rng.gen_range(0..N);
+                let y = rng.gen_range(0..N);
+                let z = rng.gen_range(0..N);
+                chunk.data[x][y][z] = GLOWSTONE;
+            }
+            chunk
+        })
+        .collect();
+    // Lit from the top down, chunk by chunk, as the light to compare against
+    let mut expected = world();
+    for y in (SKY - 2..=SKY + 1).rev() {
+        for (coord, chunk) in coords.iter().zip(&chunks) {
+            if coord.1 == y {
+                expected.insert(*coord, chunk.clone());
+            }
+        }
+    }
+    // Whatever order the chunks come in
+    let mut order: Vec<usize> = (0..coords.len()).collect();
+    for _ in 0..3 {
+        order.shuffle(&mut rng);
+        let mut world = world();
+        for i in &order {
+            insert_lit(&mut world, coords[*i], chunks[*i].clone());
+        }
+        same_light(&world, &expected, &coords);
+    }
+}
+
+#[test]
+fn chunks_lit_on_their_own_are_shaded_by_blocks_above_them() {
+    // Built on above the sky layer, so the chunk below isn't open to the sky after all
+    let mut roof = empty();
+    for x in 4..12 {
+        for z in 4..12 {
+            roof.data[x][0][z] = ROCK;
+        }
+    }
+    let coords = [(0, SKY + 1, 0), (0, SKY, 0)];
+    let mut expected = world();
+    let mut world = world();
+    for (coord, chunk) in coords.iter().zip(vec![roof.clone(), empty()]) {
+        expected.insert(*coord, chunk.clone());
+        insert_lit(&mut world, *coord, chunk);
+    }
+    same_light(&world, &expected, &coords);
+    let top = (SKY + 1) * N as i32 - 1;
+    assert_eq!(sky(&world, 8, top, 8), MAX_LIGHT - 4);
+    assert_eq!(sky(&world, 2, top, 8), MAX_LIGHT);
+
+    // Putting the roof on afterwards takes the sky light back out from under it
+    let mut late = self::world();
+    let mut late_lit = self::world();
+    for (coord, chunk) in coords.iter().zip(vec![roof, empty()]).rev() {
+        late.insert(*coord, chunk.clone());
+        insert_lit(&mut late_lit, *coord, chunk);
+    }
+    same_light(&late, &expected, &coords);
+    same_light(&late_lit, &expected, &coords);
+}
@@ -0,0 +1,436 @@
+mod common;
+
+use common::blocks;
+use engine3d::blocks::BlockRegistry;
+use engine3d::light::LightChunk;
+use engine3d::mesher::{chunk_mesh, greedy_mesh, quad_axes, Face, Neighborhood, Quad};
+use engine3d::voxel::{Chunk, CHUNK_SIZE};
+use rand::{Rng, SeedableRng};
+
+const N: usize = CHUNK_SIZE;
+
+const ROCK: u8 = 1;
+const GLASS: u8 = 2;
+const LEAVES: u8 = 3;
+
+// A registry with one block of each RenderMode
+fn see_through() -> BlockRegistry {
+    BlockRegistry::from_json(
+        r#"[
+            { "id": 0, "name": "air", "solid": false, "transparent": true },
+            { "id": 1, "name": "rock", "textures": { "all": "rock.png" } },
+            { "id": 2, "name": "glass", "textures": { "all": "rock.png" }, "transparent": true, "render": "translucent" },
+            { "id": 3, "name": "leaves", "textures": { "all": "grass.png" }, "transparent": true, "render": "cutout" }
+        ]"#,
+    )
+    .unwrap()
+}
+
+fn empty() -> Chunk {
+    Chunk {
+        data: [[[0; N]; N]; N],
+    }
+}
+
+fn full(voxel: u8) -> Chunk {
+    Chunk {
+        data: [[[voxel; N]; N]; N],
+    }
+}
+
+fn count(quads: &[Quad], face: Face) -> usize {
+    quads.iter().filter(|quad| quad.face == face).count()
+}
+
+// Number of faces that touch air (or the edge of the chunk), counted one voxel at a time
+fn visible_faces(chunk: &Chunk) -> usize {
+    let mut faces = 0;
+    for x in 0..N {
+        for y in 0..N {
+            for z in 0..N {
+                if chunk.data[x][y][z] == 0 {
+                    continue;
+                }
+                for face in Face::ALL.iter() {
+                    let (nx, ny, nz) = face.normal();
+                    let (nx, ny, nz) = (x as i32 + nx, y as i32 + ny, z as i32 + nz);
+                    let inside = [nx, ny, nz].iter().all(|c| (0..N as i32).contains(c));
+                    if !inside || chunk.data[nx as usize][ny as usize][nz as usize] == 0 {
+                        faces += 1;
+                    }
+                }
+            }
+        }
+    }
+    faces
+}
+
+fn area(quads: &[Quad]) -> usize {
+    quads.iter().map(|quad| quad.size[0] * quad.size[1]).sum()
+}
+
+#[test]
+fn empty_chunk_has_no_quads() {
+    assert!(greedy_mesh(&blocks(), &empty(), &[None; 6], None).is_empty());
+}
+
+#[test]
+fn single_voxel_has_one_quad_per_face() {
+    let mut chunk = empty();
+    chunk.data[5][6][7] = 3;
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    assert_eq!(quads.len(), 6);
+    for face in Face::ALL.iter() {
+        assert_eq!(count(&quads, *face), 1);
+    }
+    assert!(quads
+        .iter()
+        .all(|quad| quad.size == [1, 1] && quad.origin == [5, 6, 7] && quad.voxel == 3));
+}
+
+#[test]
+fn full_chunk_merges_into_six_quads() {
+    let chunk = full(2);
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    assert_eq!(quads.len(), 6);
+    assert!(quads.iter().all(|quad| quad.size == [N, N]));
+}
+
+#[test]
+fn bar_merges_along_its_length() {
+    let mut chunk = empty();
+    for x in 2..10 {
+        chunk.data[x][4][4] = 1;
+    }
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    assert_eq!(quads.len(), 6);
+    assert_eq!(area(&quads), 8 * 4 + 2);
+}
+
+#[test]
+fn materials_are_not_merged() {
+    // A one voxel thick floor, split in half between two materials
+    let mut chunk = empty();
+    for x in 0..N {
+        for z in 0..N {
+            chunk.data[x][0][z] = if x < N / 2 { 1 } else { 2 };
+        }
+    }
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    assert_eq!(count(&quads, Face::PosY), 2);
+    assert_eq!(count(&quads, Face::NegY), 2);
+    // The ends of the floor along x are each a single material
+    assert_eq!(count(&quads, Face::PosX), 1);
+    assert_eq!(count(&quads, Face::NegX), 1);
+    // The sides along z show both materials
+    assert_eq!(count(&quads, Face::PosZ), 2);
+    assert_eq!(count(&quads, Face::NegZ), 2);
+    assert_eq!(quads.len(), 10);
+}
+
+#[test]
+fn checkerboard_cannot_merge() {
+    let mut chunk = empty();
+    let mut voxels = 0;
+    for x in 0..N {
+        for y in 0..N {
+            for z in 0..N {
+                if (x + y + z) % 2 == 0 {
+                    chunk.data[x][y][z] = 4;
+                    voxels += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(
+        greedy_mesh(&blocks(), &chunk, &[None; 6], None).len(),
+        voxels * 6
+    );
+}
+
+#[test]
+fn hidden_faces_are_culled() {
+    // Two voxels side by side only show 10 faces, merged into 6 quads
+    let mut chunk = empty();
+    chunk.data[3][3][3] = 1;
+    chunk.data[3][4][3] = 1;
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    assert_eq!(quads.len(), 6);
+    assert_eq!(area(&quads), 10);
+}
+
+#[test]
+fn quads_cover_every_visible_face_exactly_once() {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    for _ in 0..20 {
+        let mut chunk = empty();
+        for x in 0..N {
+            for y in 0..N {
+                for z in 0..N {
+                    if rng.gen_bool(0.4) {
+                        chunk.data[x][y][z] = rng.gen_range(1..=3);
+                    }
+                }
+            }
+        }
+        let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+        assert_eq!(area(&quads), visible_faces(&chunk));
+        assert!(quads.len() <= visible_faces(&chunk));
+    }
+}
+
+#[test]
+fn mesh_has_four_vertices_and_six_indices_per_quad() {
+    let mut chunk = empty();
+    chunk.data[0][0][0] = 1;
+    chunk.data[8][8][8] = 2;
+    chunk.data[9][8][8] = 2;
+    let neighborhood = Neighborhood {
+        chunk,
+        neighbors: Default::default(),
+        light: LightChunk::default(),
+        neighbor_light: Default::default(),
+    };
+    let mesh = chunk_mesh(&blocks(), &neighborhood, (0, 0, 0)).opaque;
+    assert_eq!(mesh.vertices.len(), 12 * 4);
+    assert_eq!(mesh.indices.len(), 12 * 6);
+}
+
+#[test]
+fn faces_use_their_own_texture_layer() {
+    let blocks = blocks();
+    let mut chunk = empty();
+    chunk.data[8][8][8] = 1;
+    let neighborhood = Neighborhood {
+        chunk,
+        neighbors: Default::default(),
+        light: LightChunk::default(),
+        neighbor_light: Default::default(),
+    };
+    let mesh = chunk_mesh(&blocks, &neighborhood, (0, 0, 0)).opaque;
+    // Quads come out in Face::ALL order, one per face of the lone voxel
+    for (face, vertices) in Face::ALL.iter().zip(mesh.vertices.chunks(4)) {
+        let layer = blocks.face_texture(1, *face).unwrap() as u32;
+        assert!(vertices.iter().all(|vertex| vertex.layer == layer));
+    }
+    // Grass has a dirt bottom
+    assert_ne!(
+        blocks.face_texture(1, Face::PosY),
+        blocks.face_texture(1, Face::NegY)
+    );
+}
+
+#[test]
+fn solid_neighbors_hide_border_faces() {
+    let chunk = full(1);
+    let neighbor = full(3);
+    let neighbors = [Some(&neighbor); 6];
+    assert!(greedy_mesh(&blocks(), &chunk, &neighbors, None).is_empty());
+
+    // Only the side without a neighbour is left
+    let mut neighbors = neighbors;
+    neighbors[Face::NegY.index()] = None;
+    let quads = greedy_mesh(&blocks(), &chunk, &neighbors, None);
+    assert_eq!(quads.len(), 1);
+    assert_eq!(quads[0].face, Face::NegY);
+    assert_eq!(quads[0].size, [N, N]);
+}
+
+#[test]
+fn neighbors_only_hide_faces_they_touch() {
+    // The neighbour above is solid except for a hole right above voxel (3, 15, 4)
+    let chunk = full(2);
+    let mut above = full(1);
+    above.data[3][0][4] = 0;
+    let mut neighbors = [None; 6];
+    neighbors[Face::PosY.index()] = Some(&above);
+    let quads = greedy_mesh(&blocks(), &chunk, &neighbors, None);
+    let top: Vec<&Quad> = quads
+        .iter()
+        .filter(|quad| quad.face == Face::PosY)
+        .collect();
+    assert_eq!(top.len(), 1);
+    assert_eq!(top[0].origin, [3, N - 1, 4]);
+    assert_eq!(top[0].size, [1, 1]);
+    // The other five sides have no neighbours, so they stay whole
+    assert_eq!(quads.len(), 6);
+}
+
+#[test]
+fn empty_neighbors_leave_border_faces_visible() {
+    let chunk = full(1);
+    let air = empty();
+    let neighbors = [Some(&air); 6];
+    assert_eq!(greedy_mesh(&blocks(), &chunk, &neighbors, None).len(), 6);
+}
+
+// Does the quad cover the face of the voxel at pos?
+fn covers(quad: &Quad, pos: [usize; 3]) -> bool {
+    let (u, v) = quad_axes(quad.face);
+    pos[quad.face.axis()] == quad.origin[quad.face.axis()]
+        && (quad.origin[u]..quad.origin[u] + quad.size[0]).contains(&pos[u])
+        && (quad.origin[v]..quad.origin[v] + quad.size[1]).contains(&pos[v])
+}
+
+#[test]
+fn open_faces_are_not_occluded() {
+    let mut chunk = empty();
+    chunk.data[5][6][7] = 3;
+    assert!(greedy_mesh(&blocks(), &chunk, &[None; 6], None)
+        .iter()
+        .all(|quad| quad.ao == [3; 4]));
+}
+
+#[test]
+fn faces_next_to_a_wall_are_occluded() {
+    // A floor with a single voxel sitting on it
+    let mut chunk = empty();
+    for x in 0..N {
+        for z in 0..N {
+            chunk.data[x][0][z] = 1;
+        }
+    }
+    chunk.data[8][1][8] = 1;
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    let floor_top = |pos| {
+        quads
+            .iter()
+            .find(|quad| quad.face == Face::PosY && covers(quad, pos))
+            .unwrap()
+    };
+    // The floor right next to the voxel is darker on the side touching it
+    let beside = floor_top([9, 0, 8]);
+    assert!(beside.ao.contains(&2) && beside.ao.contains(&3));
+    // Far away from it the floor is open
+    assert_eq!(floor_top([0, 0, 0]).ao, [3; 4]);
+    // The floor can't merge across the differently shaded faces, but the voxel itself is unaffected
+    assert!(quads.iter().filter(|quad| quad.face == Face::PosY).count() > 2);
+    assert_eq!(floor_top([8, 1, 8]).ao, [3; 4]);
+}
+
+#[test]
+fn inside_corners_are_darkest() {
+    // Two walls meeting on top of a floor
+    let mut chunk = empty();
+    for a in 0..4 {
+        for b in 0..4 {
+            chunk.data[a][0][b] = 1;
+        }
+        chunk.data[a][1][0] = 1;
+        chunk.data[0][1][a] = 1;
+    }
+    let quads = greedy_mesh(&blocks(), &chunk, &[None; 6], None);
+    let corner = quads
+        .iter()
+        .find(|quad| quad.face == Face::PosY && covers(quad, [1, 0, 1]))
+        .unwrap();
+    assert!(corner.ao.contains(&0));
+}
+
+#[test]
+fn faces_behind_see_through_blocks_are_kept() {
+    let mut chunk = empty();
+    chunk.data[8][8][8] = ROCK;
+    chunk.data[9][8][8] = GLASS;
+    chunk.data[10][8][8] = GLASS;
+    chunk.data[8][9][8] = LEAVES;
+    chunk.data[8][10][8] = LEAVES;
+    let quads = greedy_mesh(&see_through(), &chunk, &[None; 6], None);
+    let faces = |voxel: u8| -> usize {
+        area(
+            &quads
+                .iter()
+                .filter(|quad| quad.voxel == voxel)
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+    };
+    // The rock can be seen through the glass and the leaves
+    assert_eq!(faces(ROCK), 6);
+    // Glass doesn't have faces inside of itself, and the rock hides one end
+    assert_eq!(faces(GLASS), 4 * 2 + 1);
+    // Leaves can be seen through from one to the next, but the rock still hides one
+    assert_eq!(faces(LEAVES), 6 * 2 - 1);
+}
+
+#[test]
+fn chunk_meshes_are_split_by_render_mode() {
+    let mut chunk = empty();
+    chunk.data[2][2][2] = ROCK;
+    chunk.data[6][2][2] = GLASS;
+    chunk.data[10][2][2] = LEAVES;
+    let neighborhood = Neighborhood {
+        chunk,
+        neighbors: Default::default(),
+        light: LightChunk::default(),
+        neighbor_light: Default::default(),
+    };
+    let meshes = chunk_mesh(&see_through(), &neighborhood, (0, 0, 0));
+    for mesh in [&meshes.opaque, &meshes.cutout, &meshes.translucent].iter() {
+        assert_eq!(mesh.vertices.len(), 6 * 4);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+    }
+}
+
+#[test]
+fn translucent_faces_sort_back_to_front() {
+    let mut chunk = empty();
+    chunk.data[2][2][2] = GLASS;
+    chunk.data[12][2][2] = GLASS;
+    let neighborhood = Neighborhood {
+        chunk,
+        neighbors: Default::default(),
+        light: LightChunk::default(),
+        neighbor_light: Default::default(),
+    };
+    let mesh = chunk_mesh(&see_through(), &neighborhood, (0, 0, 0)).translucent;
+    let center = |quad: &[u32]| -> f32 {
+        quad.iter()
+            .map(|i| mesh.vertices[*i as usize].position[0])
+            .sum::<f32>()
+            / quad.len() as f32
+    };
+    // Looking from far along +x, so the further a quad is along -x the sooner it's drawn
+    let indices = mesh.sorted_back_to_front([1000.0, 0.0, 0.0]);
+    assert_eq!(indices.len(), mesh.indices.len());
+    let centers: Vec<f32> = indices.chunks(6).map(center).collect();
+    assert!(centers.windows(2).all(|pair| pair[0] <= pair[1]));
+    // And the other way around from -x
+    let indices = mesh.sorted_back_to_front([-1000.0, 0.0, 0.0]);
+    let centers: Vec<f32> = indices.chunks(6).map(center).collect();
+    assert!(centers.windows(2).all(|pair| pair[0] >= pair[1]));
+}
+
+#[test]
+fn leaves_and_glass_are_drawn_cut_out_and_see_through() {
+    let blocks = blocks();
+    let leaves = blocks.id("leaves").unwrap();
+    let glass = blocks.id("glass").unwrap();
+    let rock = blocks.id("rock").unwrap();
+    let mut chunk = empty();
+    chunk.data[2][2][2] = rock;
+    chunk.data[6][2][2] = leaves;
+    chunk.data[10][2][2] = glass;
+    let neighborhood = Neighborhood {
+        chunk,
+        neighbors: Default::default(),
+        light: LightChunk::default(),
+        neighbor_light: Default::default(),
+    };
+    let meshes = chunk_mesh(&blocks, &neighborhood, (0, 0, 0));
+    // Each block's six faces end up in the mesh for its render mode, with its own texture
+    for (mesh, id) in [
+        (&meshes.opaque, rock),
+        (&meshes.cutout, leaves),
+        (&meshes.translucent, glass),
+    ]
+    .iter()
+    {
+        assert_eq!(mesh.vertices.len(), 6 * 4);
+        let layer = blocks.face_texture(*id, Face::PosY).unwrap();
+        assert!(mesh
+            .vertices
+            .iter()
+            .all(|vertex| vertex.layer as usize == layer));
+    }
+}
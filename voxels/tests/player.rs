@@ -0,0 +1,428 @@
+mod common;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use common::{blocks, empty_chunk, empty_world, temp_dir, N, ROCK, SIZE};
+use engine3d::camera::Camera;
+use engine3d::geom::BBox;
+use engine3d::player::{Player, PlayerInput, PlayerParams};
+use engine3d::save::{SaveDir, WorldMeta};
+use engine3d::world::World;
+const HALF_Y: f32 = SIZE * 0.9;
+const DT: f32 = 1.0 / 60.0;
+
+// Empty chunks around the origin with a rock floor just under y = 0
+fn flat_world() -> World {
+    floor_world(|_, _| true)
+}
+
+// Like flat_world, but only with floor in the columns of voxels (x, z) that has_floor picks
+fn floor_world(has_floor: impl Fn(i32, i32) -> bool) -> World {
+    let mut world = empty_world();
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let mut chunk = empty_chunk();
+                if y == -1 {
+                    for (i, column) in chunk.data.iter_mut().enumerate() {
+                        for (j, voxel) in column[N - 1].iter_mut().enumerate() {
+                            if has_floor(x * N as i32 + i as i32, z * N as i32 + j as i32) {
+                                *voxel = ROCK;
+                            }
+                        }
+                    }
+                }
+                world.insert((x, y, z), chunk);
+            }
+        }
+    }
+    world
+}
+
+// A player standing (or hanging) with their feet at a height, and a camera looking down -z
+fn player(feet: f32) -> (Player, Camera) {
+    let player = Player::new(
+        BBox {
+            center: Point3::new(2.0, feet + HALF_Y, 2.0),
+            half_extents: Vector3::new(0.3, 0.9, 0.3) * SIZE,
+        },
+        1.62 * SIZE,
+    );
+    let eye = player.eye();
+    let camera = Camera {
+        eye,
+        target: eye + Vector3::new(0.0, 0.0, -1.0),
+        up: Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 90.0,
+        znear: 0.1,
+        zfar: 100.0,
+    };
+    (player, camera)
+}
+
+fn feet(player: &Player) -> f32 {
+    player.hitbox.center.y - player.hitbox.half_extents.y
+}
+
+// Runs the player for a number of seconds, returning their feet's height after every step
+fn simulate(
+    player: &mut Player,
+    camera: &mut Camera,
+    world: &World,
+    seconds: f32,
+    dt: f32,
+) -> Vec<f32> {
+    (0..(seconds / dt).round() as usize)
+        .map(|_| {
+            player.update(camera, world, dt);
+            feet(player)
+        })
+        .collect()
+}
+
+#[test]
+fn falls_faster_until_terminal_velocity() {
+    let world = flat_world();
+    let (mut player, mut camera) = player(40.0);
+    let params = PlayerParams::default();
+    let t = 0.25;
+    let heights = simulate(&mut player, &mut camera, &world, t, DT);
+    // Stepping adds up to a bit more than the exact fall, by about one step's worth of speed
+    let fallen = 40.0 - heights.last().unwrap();
+    let exact = 0.5 * params.gravity * t * t;
+    assert!(fallen >= exact && fallen - exact <= params.gravity * t * DT);
+    assert!((player.vy + params.gravity * t).abs() < 1e-3);
+    // Well before reaching the floor the fall stops speeding up
+    simulate(&mut player, &mut camera, &world, 0.5, DT);
+    assert_eq!(player.vy, -params.terminal_velocity);
+    assert!(feet(&player) > 0.0);
+    // And the floor stops it
+    simulate(&mut player, &mut camera, &world, 5.0, DT);
+    assert!(feet(&player).abs() < 1e-3);
+    assert_eq!(player.vy, 0.0);
+    assert!(player.can_jump);
+}
+
+#[test]
+fn jumps_as_high_as_the_params_say() {
+    let world = flat_world();
+    // Falling as fast as the jump started, so coming down takes as long as going up
+    let earth = PlayerParams {
+        terminal_velocity: 18.0,
+        ..PlayerParams::default()
+    };
+    let moon = PlayerParams {
+        gravity: 6.0,
+        ..earth.clone()
+    };
+    for params in vec![earth, moon] {
+        let (mut player, mut camera) = player(0.0);
+        player.params = params.clone();
+        simulate(&mut player, &mut camera, &world, 0.1, DT);
+        assert!(player.can_jump);
+
+        player.input.jump = true;
+        player.update(&mut camera, &world, DT);
+        player.input.jump = false;
+        let heights = simulate(&mut player, &mut camera, &world, 10.0, DT);
+        let peak = heights.iter().cloned().fold(0.0, f32::max);
+        let expected = params.jump_speed.powi(2) / (2.0 * params.gravity);
+        assert!(
+            (peak - expected).abs() < params.jump_speed * DT,
+            "{} vs {}",
+            peak,
+            expected
+        );
+        // Back on the ground after about twice as long as it takes gravity to stop the jump
+        let airtime = heights.iter().position(|h| *h < 1e-3).unwrap() as f32 * DT;
+        let expected = 2.0 * params.jump_speed / params.gravity;
+        assert!(
+            (airtime - expected).abs() < 3.0 * DT,
+            "{} vs {}",
+            airtime,
+            expected
+        );
+        assert!(player.can_jump);
+    }
+}
+
+#[test]
+fn walking_speeds_up_and_slows_down() {
+    let world = flat_world();
+    let (mut player, mut camera) = player(0.0);
+    let params = player.params.clone();
+    let ramp = params.walk_speed / params.acceleration;
+    player.input.forward = true;
+    simulate(&mut player, &mut camera, &world, ramp / 2.0, DT);
+    assert!(player.vz < 0.0 && -player.vz < params.walk_speed);
+    simulate(&mut player, &mut camera, &world, ramp, DT);
+    assert!((player.vz + params.walk_speed).abs() < 1e-4);
+    assert_eq!(player.vx, 0.0);
+
+    // Letting go slides to a stop
+    player.input.forward = false;
+    let start = player.hitbox.center.z;
+    simulate(&mut player, &mut camera, &world, 1.0, DT);
+    assert_eq!(player.vz, 0.0);
+    let slid = start - player.hitbox.center.z;
+    let expected = params.walk_speed.powi(2) / (2.0 * params.friction);
+    assert!(
+        (slid - expected).abs() < params.walk_speed * DT,
+        "{} vs {}",
+        slid,
+        expected
+    );
+    // The camera came along
+    assert_eq!(camera.eye, player.eye());
+}
+
+#[test]
+fn same_path_at_any_frame_rate() {
+    let world = flat_world();
+    // Walks diagonally and jumps, landing after about a second
+    let run = |dt: f32| {
+        let (mut player, mut camera) = player(0.0);
+        simulate(&mut player, &mut camera, &world, 0.1, dt);
+        player.input.forward = true;
+        player.input.right = true;
+        player.input.jump = true;
+        let heights = simulate(&mut player, &mut camera, &world, 0.9, dt);
+        (player.hitbox.center, heights)
+    };
+    let (slow, slow_heights) = run(1.0 / 30.0);
+    let (fast, fast_heights) = run(1.0 / 240.0);
+    assert!((slow.x - fast.x).abs() < 1.0, "{:?} vs {:?}", slow, fast);
+    assert!((slow.z - fast.z).abs() < 1.0, "{:?} vs {:?}", slow, fast);
+    // The fast run takes 8 steps for every one of the slow run's
+    for (i, slow) in slow_heights.iter().enumerate() {
+        let fast = fast_heights[i * 8 + 7];
+        assert!(
+            (slow - fast).abs() < 0.75,
+            "{} vs {} at step {}",
+            slow,
+            fast,
+            i
+        );
+    }
+}
+
+#[test]
+fn wish_direction_is_always_a_unit_or_nothing() {
+    let look = Vector3::new(3.0, -2.0, 0.0);
+    let keys = |forward, backward, left, right| PlayerInput {
+        forward,
+        backward,
+        left,
+        right,
+        ..PlayerInput::default()
+    };
+    assert_eq!(
+        keys(true, false, false, false).wish_direction(look),
+        Vector3::new(1.0, 0.0, 0.0)
+    );
+    // Looking along +x, right is +z
+    let diagonal = keys(true, false, false, true).wish_direction(look);
+    assert!((diagonal.magnitude() - 1.0).abs() < 1e-6);
+    assert!((diagonal.x - diagonal.z).abs() < 1e-6 && diagonal.x > 0.0);
+    // Opposite keys cancel out
+    assert_eq!(
+        keys(true, true, false, false).wish_direction(look),
+        Vector3::new(0.0, 0.0, 0.0)
+    );
+    assert_eq!(
+        keys(true, true, true, true).wish_direction(look),
+        Vector3::new(0.0, 0.0, 0.0)
+    );
+    // Looking straight down there's no way forward
+    assert_eq!(
+        keys(true, false, false, false).wish_direction(Vector3::new(0.0, -1.0, 0.0)),
+        Vector3::new(0.0, 0.0, 0.0)
+    );
+}
+
+#[test]
+fn walking_diagonally_is_no_faster() {
+    let world = flat_world();
+    let (mut player, mut camera) = player(0.0);
+    player.input.forward = true;
+    player.input.left = true;
+    let start = player.hitbox.center;
+    simulate(&mut player, &mut camera, &world, 1.0, DT);
+    let speed = Vector3::new(player.vx, 0.0, player.vz).magnitude();
+    assert!((speed - player.params.walk_speed).abs() < 1e-3);
+    assert!(player.vx < 0.0 && player.vz < 0.0);
+    let walked = player.hitbox.center - start;
+    assert!(walked.magnitude() < player.params.walk_speed);
+}
+
+#[test]
+fn less_control_in_the_air() {
+    let world = flat_world();
+    let params = PlayerParams::default();
+    let t = 0.05;
+    let speed_after = |feet: f32| {
+        let (mut player, mut camera) = player(feet);
+        simulate(&mut player, &mut camera, &world, 0.1, DT);
+        player.input.forward = true;
+        simulate(&mut player, &mut camera, &world, t, DT);
+        -player.vz
+    };
+    assert!((speed_after(0.0) - params.acceleration * t).abs() < 1e-3);
+    let in_the_air = speed_after(40.0);
+    assert!((in_the_air - params.acceleration * params.air_control * t).abs() < 1e-3);
+
+    // Letting go mid-jump keeps most of the speed until landing
+    let (mut player, mut camera) = player(0.0);
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    player.input.forward = true;
+    simulate(&mut player, &mut camera, &world, 0.2, DT);
+    player.input.jump = true;
+    player.update(&mut camera, &world, DT);
+    player.input = PlayerInput::default();
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    assert!(!player.can_jump);
+    assert!(-player.vz > params.walk_speed / 2.0);
+    simulate(&mut player, &mut camera, &world, 2.0, DT);
+    assert_eq!(player.vz, 0.0);
+}
+
+#[test]
+fn sprinting_is_faster_and_widens_the_view() {
+    let world = flat_world();
+    let (mut player, mut camera) = player(0.0);
+    let params = player.params.clone();
+    let fov = camera.fovy;
+    player.input.forward = true;
+    player.input.sprint = true;
+    simulate(&mut player, &mut camera, &world, 1.0, DT);
+    assert!(player.is_sprinting());
+    assert!((player.vz + params.sprint_speed).abs() < 1e-3);
+    assert!((camera.fovy - (fov + params.sprint_fov)).abs() < 1e-3);
+
+    // Only moving forward counts
+    player.input.forward = false;
+    player.input.left = true;
+    simulate(&mut player, &mut camera, &world, 1.0, DT);
+    assert!(!player.is_sprinting());
+    assert!((player.vx + params.walk_speed).abs() < 1e-3);
+    assert!((camera.fovy - fov).abs() < 1e-3);
+}
+
+#[test]
+fn crouching_lowers_the_eyes_and_slows_down() {
+    let world = flat_world();
+    let (mut player, mut camera) = player(0.0);
+    let params = player.params.clone();
+    let (standing, eye) = (player.hitbox, player.eye());
+    player.input.crouch = true;
+    player.input.forward = true;
+    player.input.sprint = true;
+    simulate(&mut player, &mut camera, &world, 1.0, DT);
+    assert!(player.is_crouching() && !player.is_sprinting());
+    assert!((player.vz + params.crouch_speed).abs() < 1e-3);
+    let height = standing.half_extents.y * 2.0 - params.crouch_drop;
+    assert!((player.hitbox.half_extents.y * 2.0 - height).abs() < 1e-4);
+    assert!(feet(&player).abs() < 1e-3);
+    assert!((camera.eye.y - (eye.y - params.crouch_drop)).abs() < 1e-3);
+
+    player.input = PlayerInput::default();
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    assert!(!player.is_crouching());
+    assert_eq!(player.hitbox.half_extents, standing.half_extents);
+    assert!((camera.eye.y - eye.y).abs() < 1e-3);
+}
+
+#[test]
+fn cant_stand_up_under_a_low_ceiling() {
+    let mut world = flat_world();
+    let (mut player, mut camera) = player(0.0);
+    // Crouched low enough to fit under a block one voxel up
+    player.params.crouch_drop = SIZE;
+    player.input.crouch = true;
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    let above = World::voxel_center([0, 1, 0]);
+    world.set_voxel(above, ROCK);
+
+    player.input.crouch = false;
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    assert!(player.is_crouching());
+    world.set_voxel(above, 0);
+    simulate(&mut player, &mut camera, &world, 0.1, DT);
+    assert!(!player.is_crouching());
+}
+
+#[test]
+fn crouching_keeps_the_player_on_ledges() {
+    // The floor ends at z = -4, in front of the player
+    let world = floor_world(|_, z| z >= -1);
+    let edge = -SIZE - 0.3 * SIZE;
+    let (mut player, mut camera) = player(0.0);
+    player.input.crouch = true;
+    player.input.forward = true;
+    simulate(&mut player, &mut camera, &world, 2.0, DT);
+    // Right up to the edge, still standing on it
+    let z = player.hitbox.center.z;
+    assert!(z > edge && z < edge + 0.1, "{}", z);
+    assert!(feet(&player).abs() < 1e-3);
+    assert!(player.can_jump);
+
+    // Still free to walk along the edge
+    player.input.forward = false;
+    player.input.right = true;
+    simulate(&mut player, &mut camera, &world, 0.5, DT);
+    assert!(player.vx > 0.0);
+    assert!(feet(&player).abs() < 1e-3);
+
+    // Without crouching, off it goes
+    player.input.crouch = false;
+    player.input.forward = true;
+    simulate(&mut player, &mut camera, &world, 2.0, DT);
+    assert!(feet(&player) < -SIZE);
+}
+
+#[test]
+fn state_round_trips_through_the_save_file() {
+    let mut world = flat_world();
+    let (mut player, mut camera) = player(3.0);
+    // Flying, drifting along and looking up and off to the side
+    player.input.toggle_gravity = true;
+    player.update(&mut camera, &world, DT);
+    player.input.toggle_gravity = false;
+    player.vx = 1.5;
+    player.vz = -0.5;
+    player.facing_direction = Vector3::new(0.6, 0.0, -0.8);
+    let (yaw, pitch) = (2.1f32, 0.4f32);
+    let look = Vector3::new(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    );
+    camera.target = camera.eye + look;
+    let state = player.state(&camera, 4);
+
+    let saves = SaveDir::new(temp_dir("player"));
+    let mut meta = WorldMeta::new("World", 0);
+    saves.save(&mut meta, &mut world, &state).unwrap();
+    let loaded = saves.load("World", &blocks()).unwrap().player.unwrap();
+    assert_eq!(loaded, state);
+    assert_eq!(loaded.hotbar_slot, 4);
+
+    // Put back into a player somewhere else entirely
+    let (mut restored, mut restored_camera) = self::player(-10.0);
+    restored.restore(&loaded, &mut restored_camera);
+    assert_eq!(restored.hitbox.center, player.hitbox.center);
+    assert_eq!(
+        [restored.vx, restored.vy, restored.vz],
+        [player.vx, player.vy, player.vz]
+    );
+    assert_eq!(restored.facing_direction, player.facing_direction);
+    assert_eq!(restored_camera.eye, camera.eye);
+    assert_eq!(restored_camera.up, camera.up);
+    let restored_look = (restored_camera.target - restored_camera.eye).normalize();
+    assert!((restored_look.z.atan2(restored_look.x) - yaw).abs() < 1e-4);
+    assert!((restored_look.y.asin() - pitch).abs() < 1e-4);
+    // Still flying, so the player stays put up in the air
+    restored.update(&mut restored_camera, &world, DT);
+    assert_eq!(restored.vy, 0.0);
+    assert!(!restored.state(&restored_camera, 4).do_gravity);
+}
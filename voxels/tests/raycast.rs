@@ -0,0 +1,217 @@
+mod common;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use common::{empty_world, world, SIZE};
+use engine3d::camera::Camera;
+use engine3d::collision::MAX_RAY_DIST;
+use engine3d::render::REACH;
+use engine3d::world::World;
+use rand::{Rng, SeedableRng};
+
+#[test]
+fn hits_the_face_facing_the_ray() {
+    let world = world(&[[5, 0, 0]]);
+    let origin = World::voxel_center([0, 0, 0]);
+    let hit = world
+        .raycast(origin, Vector3::new(1.0, 0.0, 0.0), 100.0)
+        .unwrap();
+    assert_eq!(hit.voxel, [5, 0, 0]);
+    assert_eq!(hit.face_normal, [-1, 0, 0]);
+    assert!((hit.distance - (5.0 * SIZE - origin.x)).abs() < 1e-4);
+    assert_eq!(hit.adjacent(), [4, 0, 0]);
+
+    // From the other side and from above
+    let hit = world
+        .raycast(
+            World::voxel_center([9, 0, 0]),
+            Vector3::new(-1.0, 0.0, 0.0),
+            100.0,
+        )
+        .unwrap();
+    assert_eq!(hit.face_normal, [1, 0, 0]);
+    assert_eq!(hit.adjacent(), [6, 0, 0]);
+    let hit = world
+        .raycast(
+            World::voxel_center([5, 7, 0]),
+            Vector3::new(0.0, -3.0, 0.0),
+            100.0,
+        )
+        .unwrap();
+    assert_eq!(hit.face_normal, [0, 1, 0]);
+    assert_eq!(hit.adjacent(), [5, 1, 0]);
+}
+
+#[test]
+fn stops_at_the_max_distance() {
+    let world = world(&[[5, 0, 0]]);
+    let origin = World::voxel_center([0, 0, 0]);
+    let dir = Vector3::new(1.0, 0.0, 0.0);
+    assert!(world.raycast(origin, dir, 4.0 * SIZE).is_none());
+    assert!(world.raycast(origin, dir, 5.0 * SIZE).is_some());
+}
+
+#[test]
+fn starting_inside_a_block_hits_it() {
+    let world = world(&[[2, 3, 4]]);
+    let hit = world
+        .raycast(
+            World::voxel_center([2, 3, 4]),
+            Vector3::new(0.3, 1.0, 0.0),
+            10.0,
+        )
+        .unwrap();
+    assert_eq!(hit.voxel, [2, 3, 4]);
+    assert_eq!(hit.face_normal, [0, 0, 0]);
+    assert_eq!(hit.distance, 0.0);
+}
+
+#[test]
+fn works_across_negative_chunks() {
+    let world = world(&[[-3, -2, -1]]);
+    let from = World::voxel_center([3, 2, 1]);
+    let to = World::voxel_center([-3, -2, -1]);
+    let hit = world.raycast(from, to - from, 100.0).unwrap();
+    assert_eq!(hit.voxel, [-3, -2, -1]);
+}
+
+#[test]
+fn unloaded_chunks_and_zero_rays_hit_nothing() {
+    let world = empty_world();
+    let origin = Point3::new(1.0, 1.0, 1.0);
+    assert!(world
+        .raycast(origin, Vector3::new(1.0, 1.0, 1.0), 1000.0)
+        .is_none());
+    let world = self::world(&[[0, 0, 0]]);
+    assert!(world
+        .raycast(origin, Vector3::new(0.0, 0.0, 0.0), 1000.0)
+        .is_none());
+}
+
+#[test]
+fn rays_that_never_end_hit_nothing() {
+    // None of these would ever step past the max distance, so they have to be turned away up front
+    let world = world(&[[5, 0, 0]]);
+    let origin = World::voxel_center([0, 0, 0]);
+    let dir = Vector3::new(1.0, 0.0, 0.0);
+    for max_dist in [f32::INFINITY, f32::NAN].iter() {
+        assert!(world.raycast(origin, dir, *max_dist).is_none());
+    }
+    for bad in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN].iter() {
+        assert!(world
+            .raycast(origin, Vector3::new(*bad, 0.0, 0.0), 100.0)
+            .is_none());
+        assert!(world
+            .raycast(origin, Vector3::new(1.0, *bad, 0.0), 100.0)
+            .is_none());
+        assert!(world
+            .raycast(Point3::new(*bad, 0.0, 0.0), dir, 100.0)
+            .is_none());
+    }
+    // Very long and very short directions still point somewhere
+    for scale in [1e-30, 1e30].iter() {
+        let hit = world.raycast(origin, dir * *scale, 100.0).unwrap();
+        assert_eq!(hit.voxel, [5, 0, 0]);
+    }
+}
+
+#[test]
+fn huge_max_distances_are_cut_down_to_a_reach() {
+    let world = world(&[[5, 0, 0]]);
+    let origin = World::voxel_center([0, 0, 0]);
+    let hit = world
+        .raycast(origin, Vector3::new(1.0, 0.0, 0.0), f32::MAX)
+        .unwrap();
+    assert_eq!(hit.voxel, [5, 0, 0]);
+    // Missing everything gives up instead of stepping through voxels for ever
+    assert!(world
+        .raycast(origin, Vector3::new(-1.0, 0.3, 0.2), f32::MAX)
+        .is_none());
+    let far = World::voxel_center([0, 0, -MAX_RAY_DIST as i32 * 2]);
+    assert!(world
+        .raycast(far, Vector3::new(0.0, 0.0, 1.0), f32::MAX)
+        .is_none());
+    // Even from so far out that stepping one voxel over gets lost to rounding
+    assert!(world
+        .raycast(
+            Point3::new(1e30, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            f32::MAX
+        )
+        .is_none());
+}
+
+#[test]
+fn never_skips_a_voxel() {
+    // Compare against marching along the ray in tiny steps, which is slow but can't miss anything
+    // wider than a step, even at corners and shallow angles
+    let mut rng = rand::rngs::StdRng::seed_from_u64(17);
+    let rocks: Vec<[i32; 3]> = (0..40)
+        .map(|_| {
+            [
+                rng.gen_range(-6..6),
+                rng.gen_range(-6..6),
+                rng.gen_range(-6..6),
+            ]
+        })
+        .collect();
+    let world = world(&rocks);
+    let max_dist = 12.0 * SIZE;
+    for _ in 0..200 {
+        let origin = Point3::new(
+            rng.gen_range(-1.0..1.0) * SIZE,
+            rng.gen_range(-1.0..1.0) * SIZE,
+            rng.gen_range(-1.0..1.0) * SIZE,
+        );
+        let dir = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0f32),
+        )
+        .normalize();
+        let step = 0.001;
+        let marched = (0..(max_dist / step) as usize)
+            .map(|i| i as f32 * step)
+            .find(|t| matches!(world.voxel(origin + dir * *t), Some(v) if v != 0));
+        let hit = world.raycast(origin, dir, max_dist);
+        match (marched, hit) {
+            (None, None) => {}
+            (Some(t), Some(hit)) => {
+                assert_eq!(hit.voxel, World::world_to_voxel(origin + dir * t));
+                assert!((hit.distance - t).abs() < 0.01, "{} vs {}", hit.distance, t);
+            }
+            (marched, hit) => panic!("marched to {:?} but the ray hit {:?}", marched, hit),
+        }
+    }
+}
+
+#[test]
+fn the_target_is_the_block_in_the_middle_of_the_view() {
+    // Two rocks in a row, a little off to the side of where the camera starts
+    let world = world(&[[3, 1, -4], [3, 1, -5]]);
+    let eye = World::voxel_center([1, 1, -1]);
+    let rock = World::voxel_center([3, 1, -4]);
+    let mut camera = Camera {
+        eye,
+        target: eye + (rock - eye) * 0.1,
+        up: Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 90.0,
+        znear: 0.1,
+        zfar: 100.0,
+    };
+    let target = world.looked_at(&camera, REACH).unwrap();
+    // The same hit as a ray straight out of the middle of the view
+    let ray = world.raycast(camera.eye, camera.target - camera.eye, REACH);
+    assert_eq!(Some(target), ray);
+    // The nearer rock, through the face turned towards the camera
+    assert_eq!(target.voxel, [3, 1, -4]);
+    assert_eq!(target.face_normal, [0, 0, 1]);
+    assert!((target.distance - (rock - eye).magnitude()).abs() < SIZE);
+
+    // Looking away, or from too far back, there's nothing to target
+    camera.target = eye - (rock - eye);
+    assert_eq!(world.looked_at(&camera, REACH), None);
+    camera.eye = rock + (eye - rock).normalize() * (REACH + SIZE);
+    camera.target = rock;
+    assert_eq!(world.looked_at(&camera, REACH), None);
+}
@@ -0,0 +1,413 @@
+mod common;
+
+use common::{blocks, empty_chunk, temp_dir, world, N, ROCK};
+use engine3d::blocks::AIR;
+use engine3d::player::PlayerState;
+use engine3d::save::{rle_decode, rle_encode, write_atomic, SaveDir, WorldMeta};
+use engine3d::world::World;
+
+// A few rocks spread over different chunks, including negative ones
+const ROCKS: [[i32; 3]; 4] = [[0, 0, 0], [3, 15, 7], [-1, -16, 5], [20, -3, -9]];
+
+fn saved_bytes() -> Vec<u8> {
+    world(&ROCKS).encode().unwrap()
+}
+
+fn error(bytes: &[u8]) -> String {
+    match World::decode(bytes, &blocks()) {
+        Ok(_) => panic!("decoded a broken save"),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+#[test]
+fn round_trips_the_edited_chunks() {
+    let world = world(&ROCKS);
+    let bytes = world.encode().unwrap();
+    let loaded = World::decode(&bytes, &blocks()).unwrap();
+    assert_eq!(loaded.generator().seed(), world.generator().seed());
+    assert_eq!(loaded.generator().params(), world.generator().params());
+    // Only the chunks with rocks in them were edited, so only they come back
+    let edited: Vec<_> = world
+        .iter()
+        .map(|(coord, _)| *coord)
+        .filter(|coord| world.is_edited(*coord))
+        .collect();
+    assert_eq!(loaded.len(), edited.len());
+    for coord in edited {
+        assert_eq!(
+            loaded.get(coord).unwrap().data,
+            world.get(coord).unwrap().data
+        );
+        assert!(loaded.is_edited(coord));
+    }
+    for rock in ROCKS.iter() {
+        assert_eq!(loaded.voxel_at(*rock), Some(ROCK));
+    }
+    // And saving it again gives the same bytes
+    assert_eq!(loaded.encode().unwrap(), bytes);
+}
+
+#[test]
+fn rejects_files_that_arent_saves() {
+    let mut bytes = saved_bytes();
+    bytes[0..4].copy_from_slice(b"PNG!");
+    assert!(error(&bytes).contains("not a world save file"));
+    assert!(error(b"").contains("too short"));
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    let mut bytes = saved_bytes();
+    bytes[4..6].copy_from_slice(&99u16.to_le_bytes());
+    assert!(error(&bytes).contains("unsupported save version 99"));
+}
+
+#[test]
+fn rejects_corrupt_files() {
+    let bytes = saved_bytes();
+    // Any flipped bit should be caught, wherever it is
+    for i in (6..bytes.len()).step_by(7) {
+        let mut corrupt = bytes.clone();
+        corrupt[i] ^= 0x10;
+        assert!(World::decode(&corrupt, &blocks()).is_err(), "byte {}", i);
+    }
+    let mut corrupt = bytes;
+    corrupt[8] ^= 1; // In the seed
+    assert!(error(&corrupt).contains("checksum mismatch"));
+}
+
+#[test]
+fn rejects_truncated_files() {
+    let bytes = saved_bytes();
+    for len in [0, 3, 8, 20, bytes.len() / 2, bytes.len() - 1].iter() {
+        assert!(
+            World::decode(&bytes[..*len], &blocks()).is_err(),
+            "{} bytes",
+            len
+        );
+    }
+}
+
+#[test]
+fn run_length_encoding_round_trips() {
+    let mut chunk = empty_chunk();
+    // 4096 voxels of air is 16 runs of 255 and one of 16
+    let mut air = [255, 0].repeat(16);
+    air.extend_from_slice(&[16, 0]);
+    assert_eq!(rle_encode(&chunk), air);
+    for x in 0..N {
+        chunk.data[x][x][N - 1 - x] = x as u8;
+    }
+    chunk.data[3][4] = [ROCK; N];
+    assert_eq!(rle_decode(&rle_encode(&chunk)).unwrap().data, chunk.data);
+}
+
+#[test]
+fn run_length_decoding_rejects_the_wrong_amount_of_voxels() {
+    let full = rle_encode(&empty_chunk());
+    assert!(rle_decode(&full).is_ok());
+    let mut overflowing = full.clone();
+    overflowing.extend_from_slice(&[1, 0]);
+    assert!(rle_decode(&overflowing).is_err());
+    // A single run running past the end
+    let mut overflowing = full[..full.len() - 2].to_vec();
+    overflowing.extend_from_slice(&[17, 0]);
+    assert!(rle_decode(&overflowing).is_err());
+    assert!(rle_decode(&full[..full.len() - 2]).is_err());
+    // Empty runs and half a pair aren't allowed either
+    assert!(rle_decode(&[&full[..], &[0, 0]].concat()).is_err());
+    assert!(rle_decode(&full[..full.len() - 1]).is_err());
+}
+
+fn player() -> PlayerState {
+    player_at(1.0)
+}
+
+// Players told apart by how far along x they are
+fn player_at(x: f32) -> PlayerState {
+    PlayerState {
+        position: [x, 2.0, 3.0],
+        velocity: [0.0; 3],
+        facing_direction: [0.0, 0.0, 1.0],
+        do_gravity: true,
+        hotbar_slot: 2,
+        look_direction: [0.0, 0.0, 1.0],
+        camera_up: [0.0, 1.0, 0.0],
+    }
+}
+
+fn save_world(saves: &SaveDir, name: &str, last_played: u64) {
+    let mut meta = WorldMeta::new(name, 0);
+    saves
+        .save(&mut meta, &mut world(&ROCKS), &player())
+        .unwrap();
+    // Saving marks the world as played just now, so backdate it afterwards for a known order
+    meta.last_played = last_played;
+    let path = saves.root().join(name).join("meta.json");
+    std::fs::write(path, serde_json::to_vec(&meta).unwrap()).unwrap();
+}
+
+#[test]
+fn lists_worlds_most_recently_played_first() {
+    let saves = SaveDir::new(temp_dir("list"));
+    assert!(saves.list().unwrap().is_empty());
+    save_world(&saves, "Old", 100);
+    save_world(&saves, "New", 300);
+    save_world(&saves, "Middle", 200);
+    // Folders without metadata aren't worlds
+    std::fs::create_dir(saves.root().join("Not a world")).unwrap();
+    let names: Vec<_> = saves.list().unwrap().into_iter().map(|m| m.name).collect();
+    assert_eq!(names, ["New", "Middle", "Old"]);
+    assert!(SaveDir::new(saves.root().join("missing"))
+        .list()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn renames_and_deletes_worlds() {
+    let saves = SaveDir::new(temp_dir("rename"));
+    save_world(&saves, "World 1", 100);
+    save_world(&saves, "World 2", 200);
+    assert_eq!(saves.unused_name(), "World 3");
+
+    // Can't rename onto another world, or rename one that isn't there
+    assert!(saves.rename("World 1", "World 2").is_err());
+    assert!(saves.rename("Nowhere", "World 3").is_err());
+    saves.rename("World 1", "Castle").unwrap();
+    assert!(!saves.exists("World 1"));
+    assert!(saves.exists("Castle"));
+    let loaded = saves.load("Castle", &blocks()).unwrap();
+    assert_eq!(loaded.meta.name, "Castle");
+    assert_eq!(loaded.world.voxel_at(ROCKS[0]), Some(ROCK));
+    assert_eq!(saves.unused_name(), "World 1");
+
+    saves.delete("Castle").unwrap();
+    assert!(!saves.exists("Castle"));
+    assert!(saves.delete("Castle").is_err());
+    let names: Vec<_> = saves.list().unwrap().into_iter().map(|m| m.name).collect();
+    assert_eq!(names, ["World 2"]);
+}
+
+#[test]
+fn world_names_cant_point_outside_of_the_save_dir() {
+    let root = temp_dir("names");
+    let saves = SaveDir::new(root.join("saves"));
+    save_world(&saves, "World 1", 100);
+    // Something next to the save dir that a bad name could reach
+    save_world(&SaveDir::new(&root), "outside", 100);
+    for name in ["", "  ", ".", "..", "../outside", "a/b", "a\\b"].iter() {
+        assert!(!saves.exists(name), "{:?}", name);
+        assert!(saves.load(name, &blocks()).is_err(), "{:?}", name);
+        assert!(saves.delete(name).is_err(), "{:?}", name);
+        assert!(saves.rename("World 1", name).is_err(), "{:?}", name);
+        assert!(saves.rename(name, "World 2").is_err(), "{:?}", name);
+        let mut meta = WorldMeta::new(name, 0);
+        assert!(saves
+            .save(&mut meta, &mut world(&ROCKS), &player())
+            .is_err());
+    }
+    assert!(saves.exists("World 1"));
+    assert!(SaveDir::new(&root).exists("outside"));
+    // Anything else is fine, spaces and all
+    save_world(&saves, "My world (2)", 100);
+    assert!(saves.exists("My world (2)"));
+}
+
+#[test]
+fn atomic_writes_keep_the_last_few_versions() {
+    let path = temp_dir("rotate").join("file");
+    for version in 1..=5 {
+        write_atomic(&path, format!("v{}", version).as_bytes(), 3).unwrap();
+    }
+    let read = |suffix: &str| std::fs::read_to_string(format!("{}{}", path.display(), suffix)).ok();
+    assert_eq!(read("").as_deref(), Some("v5"));
+    assert_eq!(read(".1").as_deref(), Some("v4"));
+    assert_eq!(read(".2").as_deref(), Some("v3"));
+    assert_eq!(read(".3").as_deref(), Some("v2"));
+    assert_eq!(read(".4"), None);
+    // Nothing is left behind from writing the file or its backup
+    assert_eq!(read(".tmp"), None);
+    assert_eq!(read(".1.tmp"), None);
+    // Without backups the file is just replaced
+    write_atomic(&path, b"v6", 0).unwrap();
+    assert_eq!(read("").as_deref(), Some("v6"));
+    assert_eq!(read(".1").as_deref(), Some("v4"));
+}
+
+// Saves the world, then adds a rock and saves the whole world again
+fn save_twice(saves: &SaveDir, meta: &mut WorldMeta) -> World {
+    let mut world = world(&ROCKS);
+    saves.save(meta, &mut world, &player_at(1.0)).unwrap();
+    world.set_voxel(World::voxel_center([1, 1, 1]), ROCK);
+    saves
+        .save_snapshot(meta, &mut world, &player_at(2.0))
+        .unwrap();
+    world
+}
+
+fn corrupt(path: std::path::PathBuf) {
+    let mut bytes = std::fs::read(&path).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0xFF;
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn recovers_from_a_corrupt_latest_save() {
+    let saves = SaveDir::new(temp_dir("recover"));
+    let mut meta = WorldMeta::new("World", 0);
+    save_twice(&saves, &mut meta);
+    let latest = saves.load("World", &blocks()).unwrap();
+    assert_eq!(latest.recovered_from, None);
+    assert_eq!(latest.world.voxel_at([1, 1, 1]), Some(ROCK));
+    assert_eq!(latest.player, Some(player_at(2.0)));
+
+    let dir = saves.root().join("World");
+    corrupt(dir.join("world.dat"));
+    let recovered = saves.load("World", &blocks()).unwrap();
+    assert_eq!(recovered.recovered_from, Some(dir.join("world.dat.1")));
+    assert_eq!(recovered.world.voxel_at(ROCKS[0]), Some(ROCK));
+    assert_eq!(recovered.world.voxel_at([1, 1, 1]), Some(AIR));
+    assert_eq!(recovered.player, Some(player_at(1.0)));
+
+    // With every copy broken there's nothing left to load
+    corrupt(dir.join("world.dat.1"));
+    assert!(saves.load("World", &blocks()).is_err());
+}
+
+#[test]
+fn players_stay_paired_with_the_world_they_were_saved_with() {
+    let saves = SaveDir::new(temp_dir("pairing"));
+    let mut meta = WorldMeta::new("World", 0);
+    let mut world = save_twice(&saves, &mut meta);
+    // Nothing changed, so only the player is saved and the world backups keep their players
+    saves.save(&mut meta, &mut world, &player_at(3.0)).unwrap();
+    assert_eq!(
+        saves.load("World", &blocks()).unwrap().player,
+        Some(player_at(3.0))
+    );
+    let dir = saves.root().join("World");
+    corrupt(dir.join("world.dat"));
+    assert_eq!(
+        saves.load("World", &blocks()).unwrap().player,
+        Some(player_at(1.0))
+    );
+}
+
+#[test]
+fn players_saved_with_a_different_world_arent_used() {
+    // As if the game stopped after writing the world file but before writing the player
+    let saves = SaveDir::new(temp_dir("mismatch"));
+    let mut meta = WorldMeta::new("World", 0);
+    save_twice(&saves, &mut meta);
+    let dir = saves.root().join("World");
+    std::fs::copy(dir.join("player.json.1"), dir.join("player.json")).unwrap();
+    std::fs::remove_file(dir.join("player.json.1")).unwrap();
+    let loaded = saves.load("World", &blocks()).unwrap();
+    assert_eq!(loaded.world.voxel_at([1, 1, 1]), Some(ROCK));
+    assert_eq!(loaded.player, None);
+}
+
+#[test]
+fn saves_only_add_the_changed_chunks_to_the_journal() {
+    let saves = SaveDir::new(temp_dir("journal"));
+    let mut meta = WorldMeta::new("World", 0);
+    let mut world = world(&ROCKS);
+    saves.save(&mut meta, &mut world, &player_at(1.0)).unwrap();
+    let dir = saves.root().join("World");
+    let snapshot = std::fs::read(dir.join("world.dat")).unwrap();
+    assert!(!dir.join("world.journal").exists());
+
+    world.set_voxel(World::voxel_center([1, 1, 1]), ROCK);
+    saves.save(&mut meta, &mut world, &player_at(2.0)).unwrap();
+    let journal = std::fs::read(dir.join("world.journal")).unwrap();
+    // One chunk changed, which is much less than the whole world
+    assert!(journal.len() < snapshot.len() / 2);
+    world.set_voxel(World::voxel_center([1, 1, 1]), AIR);
+    world.set_voxel(World::voxel_center([1, 2, 1]), ROCK);
+    saves.save(&mut meta, &mut world, &player_at(3.0)).unwrap();
+    assert_eq!(std::fs::read(dir.join("world.dat")).unwrap(), snapshot);
+    assert!(!dir.join("world.dat.1").exists());
+
+    // The newest copy of each chunk wins
+    let loaded = saves.load("World", &blocks()).unwrap();
+    assert_eq!(loaded.recovered_from, None);
+    assert_eq!(loaded.world.voxel_at([1, 1, 1]), Some(AIR));
+    assert_eq!(loaded.world.voxel_at([1, 2, 1]), Some(ROCK));
+    for rock in ROCKS.iter() {
+        assert_eq!(loaded.world.voxel_at(*rock), Some(ROCK));
+    }
+    assert_eq!(loaded.player, Some(player_at(3.0)));
+}
+
+#[test]
+fn a_journal_cut_off_partway_keeps_the_changes_before_it() {
+    let saves = SaveDir::new(temp_dir("torn"));
+    let mut meta = WorldMeta::new("World", 0);
+    let mut world = world(&ROCKS);
+    saves.save(&mut meta, &mut world, &player_at(1.0)).unwrap();
+    world.set_voxel(World::voxel_center([1, 1, 1]), ROCK);
+    saves.save(&mut meta, &mut world, &player_at(2.0)).unwrap();
+    world.set_voxel(World::voxel_center([20, 1, 1]), ROCK);
+    saves.save(&mut meta, &mut world, &player_at(3.0)).unwrap();
+
+    // As if the game stopped partway through writing the last change
+    let path = saves.root().join("World").join("world.journal");
+    let journal = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &journal[..journal.len() - 3]).unwrap();
+    let mut loaded = saves.load("World", &blocks()).unwrap();
+    assert_eq!(loaded.world.voxel_at([1, 1, 1]), Some(ROCK));
+    // Its chunk was only ever saved in the part that was cut off
+    assert_eq!(loaded.world.voxel_at([20, 1, 1]), None);
+    // The player was saved after the change that got cut off
+    assert_eq!(loaded.player, None);
+
+    // Saving again writes over the broken part
+    loaded.world.set_voxel(World::voxel_center([2, 2, 2]), ROCK);
+    saves
+        .save(&mut meta, &mut loaded.world, &player_at(4.0))
+        .unwrap();
+    let loaded = saves.load("World", &blocks()).unwrap();
+    assert_eq!(loaded.world.voxel_at([1, 1, 1]), Some(ROCK));
+    assert_eq!(loaded.world.voxel_at([2, 2, 2]), Some(ROCK));
+    assert_eq!(loaded.player, Some(player_at(4.0)));
+}
+
+#[test]
+fn the_world_file_is_written_again_once_the_journal_outgrows_it() {
+    let saves = SaveDir::new(temp_dir("compact"));
+    let mut meta = WorldMeta::new("World", 0);
+    let mut world = world(&ROCKS);
+    saves.save(&mut meta, &mut world, &player_at(0.0)).unwrap();
+    let dir = saves.root().join("World");
+    let snapshot = std::fs::read(dir.join("world.dat")).unwrap();
+    let journal_len = || std::fs::metadata(dir.join("world.journal")).map_or(0, |m| m.len());
+    let mut x = 1;
+    while std::fs::read(dir.join("world.dat")).unwrap() == snapshot {
+        assert!(journal_len() <= snapshot.len() as u64);
+        world.set_voxel(World::voxel_center([x, 1, 1]), ROCK);
+        saves
+            .save(&mut meta, &mut world, &player_at(x as f32))
+            .unwrap();
+        x += 1;
+    }
+    // The old journal went along with the old world file
+    assert_eq!(journal_len(), 0);
+    assert!(dir.join("world.journal.1").exists());
+    let loaded = saves.load("World", &blocks()).unwrap();
+    for rock in 1..x {
+        assert_eq!(loaded.world.voxel_at([rock, 1, 1]), Some(ROCK));
+    }
+    assert_eq!(loaded.player, Some(player_at((x - 1) as f32)));
+
+    // Either backup still has everything saved up to it
+    corrupt(dir.join("world.dat"));
+    let recovered = saves.load("World", &blocks()).unwrap();
+    for rock in 1..x - 1 {
+        assert_eq!(recovered.world.voxel_at([rock, 1, 1]), Some(ROCK));
+    }
+    assert_eq!(recovered.world.voxel_at([x - 1, 1, 1]), Some(AIR));
+    assert_eq!(recovered.player, Some(player_at((x - 2) as f32)));
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use common::{empty_world, seeded_world, ROCK};
+use engine3d::blocks::AIR;
+use engine3d::jobs::ChunkState;
+use engine3d::mesher::{chunk_mesh, ChunkMesh};
+use engine3d::streaming::ChunkStreamer;
+use engine3d::world::{ChunkCoord, World};
+use engine3d::world_gen::{LOAD_RADIUS, UNLOAD_RADIUS};
+use std::collections::HashSet;
+use std::time::Duration;
+
+// One worker, so jobs come back in the order they were sent
+fn streamer() -> ChunkStreamer {
+    let mut streamer = ChunkStreamer::new(1);
+    streamer.load_radius = (1, 1);
+    streamer.unload_radius = (2, 2);
+    streamer
+}
+
+// Waits for every job to come back, returning the meshes that were accepted in the order they were
+fn drain(streamer: &mut ChunkStreamer, world: &mut World) -> Vec<(ChunkCoord, ChunkMesh)> {
+    let mut meshes = vec![];
+    while streamer.in_flight() > 0 {
+        meshes.extend(streamer.receive(world));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    meshes
+}
+
+fn around((x, y, z): ChunkCoord, (horizontal, vertical): (i32, i32)) -> Vec<ChunkCoord> {
+    let mut coords = vec![];
+    for dx in -horizontal..=horizontal {
+        for dy in -vertical..=vertical {
+            for dz in -horizontal..=horizontal {
+                coords.push((x + dx, y + dy, z + dz));
+            }
+        }
+    }
+    coords
+}
+
+#[test]
+fn loading_a_saved_world_keeps_its_edits() {
+    let mut world = empty_world();
+    let coord = (0, 0, 0);
+    let voxel = [3, 4, 5];
+    world.load_chunk(coord);
+    // Whatever the terrain doesn't have there
+    let edit = if world.voxel_at(voxel) == Some(ROCK) {
+        AIR
+    } else {
+        ROCK
+    };
+    world.set_voxel(World::voxel_center(voxel), edit);
+    let mut world = World::decode(&world.encode().unwrap(), world.blocks()).unwrap();
+
+    let mut streamer = streamer();
+    streamer.reset(&mut world, coord);
+    drain(&mut streamer, &mut world);
+    assert_eq!(world.voxel_at(voxel), Some(edit));
+    assert_eq!(streamer.state(coord), ChunkState::Ready);
+}
+
+#[test]
+fn chunks_go_through_every_state_in_order() {
+    let mut world = empty_world();
+    let mut streamer = streamer();
+    let center = (0, 2, 0);
+    let coords = around(center, streamer.load_radius);
+    streamer.stream(&mut world, center);
+    for coord in &coords {
+        assert_eq!(streamer.state(*coord), ChunkState::Generating);
+    }
+    assert_eq!(streamer.in_flight(), coords.len());
+
+    // Each step either moves a chunk forward or sends it back to be meshed again
+    let allowed = [
+        (ChunkState::Generating, ChunkState::Generated),
+        (ChunkState::Generating, ChunkState::Meshing),
+        (ChunkState::Generated, ChunkState::Meshing),
+        (ChunkState::Meshing, ChunkState::Ready),
+        (ChunkState::Ready, ChunkState::Meshing),
+    ];
+    let mut states: Vec<ChunkState> = coords.iter().map(|c| streamer.state(*c)).collect();
+    let mut meshed = vec![];
+    while streamer.in_flight() > 0 {
+        meshed.extend(streamer.receive(&mut world).into_iter().map(|(c, _)| c));
+        for (coord, state) in coords.iter().zip(states.iter_mut()) {
+            let next = streamer.state(*coord);
+            if next != *state {
+                assert!(allowed.contains(&(*state, next)), "{:?} {:?}", coord, next);
+                *state = next;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for coord in &coords {
+        assert!(world.contains(*coord));
+        assert_eq!(streamer.state(*coord), ChunkState::Ready);
+        assert!(meshed.contains(coord));
+    }
+    // Nothing outside of the load radius was touched
+    assert_eq!(world.len(), coords.len());
+    assert_eq!(streamer.state((0, 2, 2)), ChunkState::Unloaded);
+}
+
+#[test]
+fn jobs_for_a_replaced_world_are_thrown_away() {
+    let mut old = empty_world();
+    let mut streamer = streamer();
+    streamer.stream(&mut old, (0, 0, 0));
+    // Swapped out before any of the old world's jobs came back
+    let mut world = seeded_world(1);
+    streamer.reset(&mut world, (0, 0, 0));
+    drain(&mut streamer, &mut world);
+    assert!(old.is_empty());
+    for coord in around((0, 0, 0), streamer.load_radius) {
+        let generated = world.generator().generate_chunk(coord);
+        assert_eq!(
+            world.get(coord).unwrap().data,
+            generated.data,
+            "{:?}",
+            coord
+        );
+        assert_eq!(streamer.state(coord), ChunkState::Ready);
+    }
+}
+
+#[test]
+fn chunks_left_behind_before_their_jobs_come_back_are_dropped() {
+    let mut world = empty_world();
+    let mut streamer = streamer();
+    streamer.stream(&mut world, (0, 0, 0));
+    // Far enough that everything around the origin is past the unload radius
+    let far = (10, 0, 0);
+    streamer.stream(&mut world, far);
+    drain(&mut streamer, &mut world);
+    for coord in around((0, 0, 0), streamer.load_radius) {
+        assert!(!world.contains(coord));
+        assert_eq!(streamer.state(coord), ChunkState::Unloaded);
+    }
+    for coord in around(far, streamer.load_radius) {
+        assert_eq!(streamer.state(coord), ChunkState::Ready);
+    }
+    assert_eq!(world.len(), around(far, streamer.load_radius).len());
+}
+
+#[test]
+fn only_the_newest_mesh_of_a_chunk_is_drawn() {
+    let mut world = empty_world();
+    let mut streamer = streamer();
+    let coord = (0, 0, 0);
+    streamer.stream(&mut world, coord);
+    drain(&mut streamer, &mut world);
+
+    // Meshed again while it's already being meshed, with a voxel changed in between
+    let again: HashSet<ChunkCoord> = [coord].iter().copied().collect();
+    streamer.request_meshes(&world, again.clone());
+    let voxel = [3, 4, 5];
+    let edit = if world.voxel_at(voxel) == Some(ROCK) {
+        AIR
+    } else {
+        ROCK
+    };
+    world.set_voxel(World::voxel_center(voxel), edit);
+    streamer.request_meshes(&world, again);
+    let meshes = drain(&mut streamer, &mut world);
+
+    let newest = chunk_mesh(world.blocks(), &world.neighborhood(coord).unwrap(), coord);
+    let drawn: Vec<&ChunkMesh> = meshes
+        .iter()
+        .filter(|(c, _)| *c == coord)
+        .map(|(_, mesh)| mesh)
+        .collect();
+    assert_eq!(drawn, vec![&newest]);
+    assert_eq!(streamer.state(coord), ChunkState::Ready);
+}
+
+#[test]
+fn streams_chunks_in_and_out_around_the_player() {
+    let mut world = empty_world();
+    let mut streamer = ChunkStreamer::new(1);
+    assert_eq!(streamer.load_radius, LOAD_RADIUS);
+    assert_eq!(streamer.unload_radius, UNLOAD_RADIUS);
+    streamer.stream(&mut world, (0, 0, 0));
+    for coord in around((0, 0, 0), LOAD_RADIUS) {
+        assert_eq!(streamer.state(coord), ChunkState::Generating);
+    }
+    drain(&mut streamer, &mut world);
+    // A chunk that's about to be left behind gets edited
+    let edited = (-1, 0, 0);
+    let voxel = [-10, 3, 3];
+    world.set_voxel(World::voxel_center(voxel), ROCK);
+
+    let center = (UNLOAD_RADIUS.0 + LOAD_RADIUS.0, 0, 0);
+    streamer.stream(&mut world, center);
+    for coord in around(center, LOAD_RADIUS) {
+        assert!(world.contains(coord) || streamer.state(coord) == ChunkState::Generating);
+    }
+    drain(&mut streamer, &mut world);
+    for coord in around(center, LOAD_RADIUS) {
+        assert_eq!(streamer.state(coord), ChunkState::Ready);
+    }
+    for coord in around((0, 0, 0), LOAD_RADIUS) {
+        let dropped = (coord.0 - center.0).abs() > UNLOAD_RADIUS.0;
+        if coord == edited {
+            // Kept, but not drawn
+            assert_eq!(streamer.state(coord), ChunkState::Generated);
+            assert_eq!(world.voxel_at(voxel), Some(ROCK));
+        } else if dropped {
+            assert!(!world.contains(coord), "{:?}", coord);
+            assert_eq!(streamer.state(coord), ChunkState::Unloaded);
+        } else {
+            // Still inside the unload radius
+            assert_eq!(streamer.state(coord), ChunkState::Ready);
+        }
+    }
+}
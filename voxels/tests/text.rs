@@ -0,0 +1,27 @@
+use engine3d::text::{text_image, TextLine, TEXT_SCALE};
+
+const WHITE: [u8; 4] = [255; 4];
+
+#[test]
+fn panel_fits_the_longest_line_with_a_margin() {
+    let image = text_image(&[TextLine::new("Hi", WHITE), TextLine::new("World", WHITE)]);
+    // Characters are 6 by 9 font pixels, counting the gaps, and the margin is one character wide
+    assert_eq!(image.width(), (5 + 2) * 6 * TEXT_SCALE);
+    assert_eq!(image.height(), (2 + 2) * 9 * TEXT_SCALE);
+    assert_eq!(text_image(&[]).width(), 2 * 6 * TEXT_SCALE);
+}
+
+#[test]
+fn draws_the_text_in_its_color() {
+    let red = [255, 0, 0, 255];
+    let lit = |text: &str| {
+        let image = text_image(&[TextLine::new(text, red)]);
+        image.pixels().filter(|p| p.0 == red).count()
+    };
+    assert_eq!(lit(" "), 0);
+    // The top of an I is 3 font pixels wide, then a 5 tall stem, then another 3 along the bottom
+    assert_eq!(lit("I"), 11 * (TEXT_SCALE * TEXT_SCALE) as usize);
+    // Lower case is drawn as capitals
+    assert_eq!(lit("i"), lit("I"));
+    assert_eq!(lit("world"), lit("WORLD"));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{empty_chunk, world, N, ROCK};
+use engine3d::blocks::AIR;
+use engine3d::world::World;
+
+const SIZE: i32 = N as i32;
+
+// Voxel coordinates on either side of the borders around chunk -1
+const EDGES: [i32; 6] = [-SIZE - 1, -SIZE, -1, 0, SIZE - 1, SIZE];
+
+// Which chunk a voxel coordinate is in, and where inside of it
+fn expected(v: i32) -> (i32, usize) {
+    match v {
+        v if v == -SIZE - 1 => (-2, N - 1),
+        v if v == -SIZE => (-1, 0),
+        -1 => (-1, N - 1),
+        0 => (0, 0),
+        v if v == SIZE - 1 => (0, N - 1),
+        v if v == SIZE => (1, 0),
+        _ => unreachable!(),
+    }
+}
+
+fn rocks(world: &World) -> usize {
+    world
+        .iter()
+        .map(|(_, chunk)| {
+            chunk
+                .data
+                .iter()
+                .flatten()
+                .flatten()
+                .filter(|voxel| **voxel == ROCK)
+                .count()
+        })
+        .sum()
+}
+
+#[test]
+fn negative_voxels_land_in_the_right_chunk() {
+    for x in EDGES.iter() {
+        for y in EDGES.iter() {
+            for z in EDGES.iter() {
+                let (chunk, voxel) = World::world_to_chunk(World::voxel_center([*x, *y, *z]));
+                let ((cx, vx), (cy, vy), (cz, vz)) = (expected(*x), expected(*y), expected(*z));
+                assert_eq!(chunk, (cx, cy, cz), "{:?}", [x, y, z]);
+                assert_eq!(voxel, (vx, vy, vz), "{:?}", [x, y, z]);
+                assert_eq!(
+                    World::world_to_voxel(World::voxel_center([*x, *y, *z])),
+                    [*x, *y, *z]
+                );
+            }
+        }
+    }
+    // Chunk corners map back onto the chunk's first voxel
+    for coord in [(-1, -1, -1), (-2, 0, 1), (3, -4, -5)].iter() {
+        assert_eq!(
+            World::world_to_chunk(World::chunk_to_world(*coord)),
+            (*coord, (0, 0, 0))
+        );
+    }
+}
+
+#[test]
+fn voxels_round_trip_across_negative_chunk_borders() {
+    let mut world = world(&[]);
+    for x in -2..=1 {
+        for y in -2..=1 {
+            for z in -2..=1 {
+                world.get_or_insert_with((x, y, z), empty_chunk);
+            }
+        }
+    }
+    for x in EDGES.iter() {
+        for y in EDGES.iter() {
+            for z in EDGES.iter() {
+                let voxel = [*x, *y, *z];
+                assert!(world.set_voxel(World::voxel_center(voxel), ROCK));
+                assert_eq!(world.voxel_at(voxel), Some(ROCK), "{:?}", voxel);
+                assert_eq!(world.voxel(World::voxel_center(voxel)), Some(ROCK));
+                // Nothing else got changed along with it
+                assert_eq!(rocks(&world), 1, "{:?}", voxel);
+                let (chunk, (vx, vy, vz)) = World::world_to_chunk(World::voxel_center(voxel));
+                assert_eq!(world.get(chunk).unwrap().data[vx][vy][vz], ROCK);
+                assert!(world.set_voxel(World::voxel_center(voxel), AIR));
+                assert_eq!(world.voxel_at(voxel), Some(AIR));
+            }
+        }
+    }
+}
+
+#[test]
+fn unloaded_chunks_have_no_voxels() {
+    let mut world = world(&[]);
+    let outside = [-SIZE * 2 - 1, 0, 0];
+    assert_eq!(world.voxel_at(outside), None);
+    assert!(!world.set_voxel(World::voxel_center(outside), ROCK));
+    assert!(!world.contains((-3, 0, 0)));
+    // Only made when it's first asked for
+    world.get_or_insert_with((-3, 0, 0), empty_chunk);
+    assert!(world.set_voxel(World::voxel_center(outside), ROCK));
+    world.get_or_insert_with((-3, 0, 0), || panic!("made the chunk twice"));
+    assert_eq!(world.voxel_at(outside), Some(ROCK));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::blocks;
+use engine3d::blocks::{BlockRegistry, BlockType};
+use engine3d::world_gen::{WorldGen, WorldGenParams};
+use std::sync::Arc;
+
+// A spread of chunks covering the bottom, middle, top and air layers, including negative coordinates
+const COORDS: [(i32, i32, i32); 6] = [
+    (0, 0, 0),
+    (2, 1, -3),
+    (-4, 2, 7),
+    (1, 3, 1),
+    (-1, 3, -1),
+    (0, 4, 0),
+];
+
+#[test]
+fn same_seed_generates_identical_chunks() {
+    let first = WorldGen::new(1234, WorldGenParams::default(), blocks());
+    let second = WorldGen::new(1234, WorldGenParams::default(), blocks());
+    for coord in COORDS.iter() {
+        assert!(
+            first.generate_chunk(*coord).data == second.generate_chunk(*coord).data,
+            "chunk {:?} differs between generators with the same seed",
+            coord
+        );
+    }
+}
+
+#[test]
+fn generating_twice_is_identical() {
+    let generator = WorldGen::new(99, WorldGenParams::default(), blocks());
+    for coord in COORDS.iter() {
+        assert!(generator.generate_chunk(*coord).data == generator.generate_chunk(*coord).data);
+    }
+}
+
+#[test]
+fn different_seeds_generate_different_terrain() {
+    let first = WorldGen::new(1, WorldGenParams::default(), blocks());
+    let second = WorldGen::new(2, WorldGenParams::default(), blocks());
+    let differs = COORDS
+        .iter()
+        .any(|coord| first.generate_chunk(*coord).data != second.generate_chunk(*coord).data);
+    assert!(differs);
+}
+
+#[test]
+fn params_change_the_terrain() {
+    let params = WorldGenParams {
+        world_height: 3,
+        ..WorldGenParams::default()
+    };
+    let generator = WorldGen::new(7, params, blocks());
+    // With 3 layers the surface is in layer 1 and everything above layer 1 is air
+    let surface = generator.generate_chunk((0, 1, 0));
+    assert!(surface
+        .data
+        .iter()
+        .flatten()
+        .flatten()
+        .any(|voxel| *voxel == 1));
+    let sky = generator.generate_chunk((0, 2, 0));
+    assert!(sky.data.iter().flatten().flatten().all(|voxel| *voxel == 0));
+}
+
+#[test]
+fn bands_are_made_of_blocks_by_name() {
+    // The same blocks, with rock and bedrock swapping ids
+    let mut swapped: Vec<BlockType> = blocks().iter().cloned().collect();
+    for block in swapped.iter_mut() {
+        block.id = match block.name.as_str() {
+            "rock" => 7,
+            "bedrock" => 3,
+            _ => block.id,
+        };
+    }
+    let swapped = Arc::new(BlockRegistry::new(swapped).unwrap());
+    let first = WorldGen::new(5, WorldGenParams::default(), blocks());
+    let second = WorldGen::new(5, WorldGenParams::default(), swapped);
+    for coord in COORDS.iter() {
+        let names = |generator: &WorldGen| -> Vec<String> {
+            let chunk = generator.generate_chunk(*coord);
+            chunk
+                .data
+                .iter()
+                .flatten()
+                .flatten()
+                .map(|id| generator.blocks().get(*id).name.clone())
+                .collect()
+        };
+        assert!(names(&first) == names(&second), "chunk {:?}", coord);
+    }
+}